@@ -0,0 +1,28 @@
+// The simulation and everything around it. The binary in main.rs runs it
+// headless or in a window.
+
+pub mod modules {
+    pub mod args;
+    pub mod clock;
+    pub mod config;
+    pub mod controller;
+    pub mod demand;
+    pub mod detectors;
+    pub mod export;
+    pub mod geometry;
+    pub mod gridlock;
+    pub mod junction;
+    pub mod lights;
+    pub mod metrics;
+    pub mod pedestrians;
+    pub mod replay;
+    pub mod roads;
+    pub mod safety;
+    pub mod scenario;
+    pub mod simulation;
+    pub mod spatial;
+    pub mod traffic;
+    pub mod vehicle;
+    #[cfg(feature = "gui")]
+    pub mod view;
+}
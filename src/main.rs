@@ -1,26 +1,195 @@
-extern crate sdl2;
+use road_intersection::modules::args::Options;
+use road_intersection::modules::config::Config;
+use road_intersection::modules::demand::DemandGenerator;
+use road_intersection::modules::export::export_metrics;
+use road_intersection::modules::metrics::{
+    print_emergency_report, print_journey_report, print_od_report, print_pedestrian_report,
+    print_queue_report, print_safety_report, print_trip_report,
+};
+use road_intersection::modules::replay::{Replay, ReplayPlayer, SimEvent};
+use road_intersection::modules::scenario::{Scenario, ScenarioPlayer};
+use road_intersection::modules::simulation::Simulation;
+use road_intersection::modules::traffic::Traffic;
+use road_intersection::modules::vehicle::VehicleClass;
+use std::path::Path;
 
-mod modules {
-    pub mod lights;
-    pub mod vehicle;
-    pub mod view;
-}
-use rand::Rng;
-
-use modules::view::*;
-use sdl2::keyboard::Keycode;
-use sdl2::{event::Event, pixels::Color};
+// the window
+#[cfg(feature = "gui")]
+use road_intersection::modules::{clock::Clock, vehicle::Position, view::*};
+#[cfg(feature = "gui")]
+use sdl2::{event::Event, keyboard::Keycode};
+#[cfg(feature = "gui")]
 use std::time::Duration;
 
-use crate::modules::vehicle::{Position, Vehicle};
+const DEFAULT_CONFIG: &str = "config.toml";
+const HEADLESS_TICKS: u64 = 3600;
+// where `E` exports metrics when no `--export` directory is given
+const DEFAULT_EXPORT_DIR: &str = "metrics";
 
 fn main() {
+    let options = Options::parse();
+
+    // the default config file is optional, one given on the command line isn't
+    let config = match &options.config {
+        Some(path) => Config::load(path),
+        None if Path::new(DEFAULT_CONFIG).exists() => Config::load(DEFAULT_CONFIG),
+        None => Ok(Config::default()),
+    }
+    .unwrap_or_else(|err| exit_with_error(&err));
+
+    let replay = options
+        .replay
+        .as_ref()
+        .map(|path| Replay::load(path).unwrap_or_else(|err| exit_with_error(&err)));
+    let scenario = options
+        .scenario
+        .as_ref()
+        .map(|path| Scenario::load(path).unwrap_or_else(|err| exit_with_error(&err)));
+
+    // a replay brings its own seed along, a scenario may
+    let seed = match (&replay, &scenario) {
+        (Some(replay), _) => Some(replay.seed),
+        (_, Some(scenario)) => options.seed.or(scenario.seed),
+        _ => options.seed,
+    }
+    .unwrap_or_else(rand::random);
+    println!("seed: {seed}");
+
+    let mut sim = Simulation::new(&config, seed);
+    let mut ticks = options.ticks;
+
+    let traffic = if let Some(replay) = replay {
+        replay.check_config(&config).unwrap_or_else(|err| {
+            let path = options.replay.as_deref().unwrap();
+            exit_with_error(&format!("cannot replay `{path}`: {err}"))
+        });
+        sim.replaying = true;
+        Traffic::Replay(ReplayPlayer::new(replay))
+    } else if let Some(scenario) = scenario {
+        scenario.check_network(&sim).unwrap_or_else(|err| {
+            let path = options.scenario.as_deref().unwrap();
+            exit_with_error(&format!("invalid scenario `{path}`: {err}"))
+        });
+        if let Some(light) = &scenario.initial_light {
+            sim.set_light(light.clone());
+        }
+        ticks = ticks.or(scenario.ticks);
+        Traffic::Scenario(ScenarioPlayer::new(&scenario))
+    } else if options.demand {
+        let generator = DemandGenerator::new(&sim, seed)
+            .unwrap_or_else(|err| exit_with_error(&format!("invalid demand: {err}")));
+        Traffic::Demand(Box::new(generator))
+    } else if options.headless {
+        Traffic::Random
+    } else {
+        Traffic::Keyboard
+    };
+
+    if options.headless {
+        run_headless(&mut sim, traffic, ticks.unwrap_or(HEADLESS_TICKS));
+    } else {
+        let export_dir = options.export.as_deref().unwrap_or(DEFAULT_EXPORT_DIR);
+        run_window(&mut sim, traffic, ticks, export_dir);
+    }
+
+    let timestep = sim.config.simulation.timestep;
+    if !sim.trips.is_empty() {
+        print_trip_report(&sim.trips, timestep);
+    }
+    if sim
+        .trips
+        .iter()
+        .any(|trip| trip.class == VehicleClass::Emergency)
+    {
+        print_emergency_report(&sim.trips, timestep);
+    }
+    if sim.junctions.len() > 1 && !sim.journeys.is_empty() {
+        print_journey_report(&sim.journeys, timestep);
+    }
+    if !sim.config.demand.od.is_empty() && !sim.journeys.is_empty() {
+        print_od_report(&sim.journeys, timestep);
+    }
+    if sim.tick > 0 {
+        print_queue_report(&sim.detectors);
+    }
+    if !sim.pedestrians_crossed.is_empty() {
+        print_pedestrian_report(&sim.pedestrians_crossed, timestep);
+    }
+    if sim.tick > 0 {
+        print_safety_report(&sim.safety.violations);
+    }
+    if !sim.gridlock.gridlocks.is_empty() {
+        let ticks: Vec<u64> = sim
+            .gridlock
+            .gridlocks
+            .iter()
+            .map(|gridlock| gridlock.tick)
+            .collect();
+        println!("{} gridlocks, at ticks {:?}", ticks.len(), ticks);
+    }
+    if !sim.uncleared_greens.is_empty() {
+        println!(
+            "{} greens started with a vehicle still in the intersection, at ticks {:?}",
+            sim.uncleared_greens.len(),
+            sim.uncleared_greens
+        );
+    }
+
+    if let Some(dir) = &options.export {
+        export(&sim, dir);
+    }
+
+    if let Some(path) = &options.record {
+        let replay = Replay {
+            seed,
+            config: Some(config.fingerprint()),
+            events: sim.events,
+        };
+        replay
+            .save(path)
+            .unwrap_or_else(|err| exit_with_error(&err));
+        println!("session recorded to {path}");
+    }
+}
+
+fn run_headless(sim: &mut Simulation, mut traffic: Traffic, ticks: u64) {
+    let mut logged = 0;
+    while sim.tick < ticks {
+        // scripted traffic ends once its last car has left the road
+        if traffic.is_finished() && sim.vehicles.is_empty() {
+            break;
+        }
+
+        traffic.feed(sim);
+        sim.step();
+        log_gridlocks(sim, &mut logged);
+    }
+
+    let spawned = sim
+        .events
+        .iter()
+        .filter(|event| matches!(event, SimEvent::Spawn { .. }))
+        .count();
+    println!(
+        "{} ticks, {} vehicles spawned, {} still on the road",
+        sim.tick,
+        spawned,
+        sim.vehicles.len()
+    );
+    if let Traffic::Demand(generator) = &traffic {
+        println!("{} arrivals still waiting to enter", generator.waiting());
+    }
+}
+
+#[cfg(feature = "gui")]
+fn run_window(sim: &mut Simulation, mut traffic: Traffic, ticks: Option<u64>, export_dir: &str) {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
+    let window = &sim.config.window;
     let window = video_subsystem
-        .window("Road Intersection", 900, 700)
-        .position(6060, 30)
+        .window("Road Intersection", window.width, window.height)
+        .position(window.x, window.y)
         // .position_centered()
         .build()
         .unwrap();
@@ -28,69 +197,76 @@ fn main() {
     let mut canvas = window.into_canvas().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let mut view = View::new(&canvas);
+    let view = View::new(&sim.config);
+    let mut clock = Clock::new(sim.config.simulation.timestep);
+    let mut logged = 0;
 
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
+                Event::Quit { .. } => break 'running,
+
                 Event::KeyDown {
-                    keycode: Some(key), ..
-                } => match key {
-                    Keycode::Escape => break 'running,
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } => export(sim, export_dir),
 
-                    Keycode::Up if view.cool_downs.bottom == 0 => {
-                        view.vehicles.push(Vehicle::new(&view, Position::Bottom));
-                        view.cool_downs.bottom = 90; // 1.5 second at 90 FPS
+                // the clock works whatever drives the traffic
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => clock.toggle_pause(),
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::Period),
+                    ..
+                } => clock.step(),
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::Equals | Keycode::Plus | Keycode::KpPlus),
+                    ..
+                } => clock.faster(),
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus | Keycode::KpMinus),
+                    ..
+                } => clock.slower(),
+
+                // the keyboard doesn't spawn anything during a replay or a scenario
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if matches!(traffic, Traffic::Keyboard | Traffic::Demand(_)) => match key {
+                    Keycode::Up => {
+                        sim.spawn(Position::Bottom);
                     }
 
-                    Keycode::Right if view.cool_downs.left == 0 => {
-                        view.vehicles.push(Vehicle::new(&view, Position::Left));
-                        view.cool_downs.left = 90;
+                    Keycode::Right => {
+                        sim.spawn(Position::Left);
                     }
 
-                    Keycode::Down if view.cool_downs.top == 0 => {
-                        view.vehicles.push(Vehicle::new(&view, Position::Top));
-                        view.cool_downs.top = 90;
+                    Keycode::Down => {
+                        sim.spawn(Position::Top);
                     }
 
-                    Keycode::Left if view.cool_downs.right == 0 => {
-                        view.vehicles.push(Vehicle::new(&view, Position::Right));
-                        view.cool_downs.right = 90;
+                    Keycode::Left => {
+                        sim.spawn(Position::Right);
                     }
 
                     Keycode::R => {
-                        let position = match rand::thread_rng().gen_range(0..=3) {
-                            0 => Position::Bottom,
-                            1 => Position::Top,
-                            2 => Position::Left,
-                            _ => Position::Right,
-                        };
-
-                        let can_spawn = match position {
-                            Position::Top if view.cool_downs.top == 0 => {
-                                view.cool_downs.top = 90;
-                                true
-                            }
-                            Position::Right if view.cool_downs.right == 0 => {
-                                view.cool_downs.right = 90;
-                                true
-                            }
-                            Position::Bottom if view.cool_downs.bottom == 0 => {
-                                view.cool_downs.bottom = 90;
-                                true
-                            }
-                            Position::Left if view.cool_downs.left == 0 => {
-                                view.cool_downs.left = 90;
-                                true
-                            }
-                            _ => false,
-                        };
-
-                        if can_spawn {
-                            view.vehicles.push(Vehicle::new(&view, position));
-                        }
+                        sim.spawn_random();
                     }
 
+                    Keycode::A => {
+                        sim.spawn_emergency();
+                    }
+
+                    Keycode::P => sim.spawn_random_pedestrian(),
+
                     _ => (),
                 },
 
@@ -98,88 +274,70 @@ fn main() {
             }
         }
 
-        view.draw(&mut canvas);
-
-        // for debugging
-        for (_, r) in &view.decision_areas {
-            canvas.set_draw_color(Color::RGB(66, 0, 0));
-            canvas.fill_rect(*r).unwrap();
-        }
-        // for (_, rect) in &view.stop_lines {
-        //     canvas.set_draw_color(Color::RGB(25, 155, 55));
-        //     canvas.draw_rect(*rect).unwrap();
-        // }
-
-        let mut vehicle_in_decision_area = false;
-
-        for vehicle in &view.vehicles {
-            for (_, area) in &view.decision_areas {
-                if vehicle.is_in_area2(area) {
-                    vehicle_in_decision_area = true;
-                    break;
-                }
-            }
-            if vehicle_in_decision_area {
+        // as many fixed steps as the wall clock moved on, whatever the frame rate
+        for _ in 0..clock.steps() {
+            if ticks.is_some_and(|ticks| sim.tick >= ticks) {
                 break;
             }
+            traffic.feed(sim);
+            sim.step();
         }
+        log_gridlocks(sim, &mut logged);
 
-        view.update_light_timing(vehicle_in_decision_area);
-
-        // increment cooldown for each direction
-        if view.cool_downs.top > 0 {
-            view.cool_downs.top -= 1;
-        }
-        if view.cool_downs.right > 0 {
-            view.cool_downs.right -= 1;
-        }
-        if view.cool_downs.bottom > 0 {
-            view.cool_downs.bottom -= 1;
-        }
-        if view.cool_downs.left > 0 {
-            view.cool_downs.left -= 1;
+        let title = format!("Road Intersection ({})", clock.status());
+        if canvas.window().title() != title {
+            canvas.window_mut().set_title(&title).unwrap();
         }
 
-        // check if a car reached the end
-        view.vehicles.retain(|vehicle| match vehicle.start {
-            Position::Top => vehicle.y <= view.height as i32, // bottom reached
-            Position::Bottom => vehicle.y + vehicle.height as i32 >= 0, // top reached
-            Position::Left => vehicle.x <= view.width as i32, // Right reached
-            Position::Right => vehicle.x + vehicle.width as i32 >= 0, // Left reached
-        });
+        view.draw(&mut canvas, sim);
+        canvas.present();
 
-        let cloned_view = view.clone();
+        if ticks.is_some_and(|ticks| sim.tick >= ticks) {
+            break 'running;
+        }
 
-        for vehicle in &mut view.vehicles {
-            decide_direction(vehicle, &cloned_view.decision_areas);
+        ::std::thread::sleep(Duration::from_millis(1));
+    }
+}
 
-            match vehicle.start {
-                Position::Top => {
-                    if vehicle.can_move(&cloned_view.clone()) {
-                        vehicle.y += 1
-                    }
-                }
-                Position::Right => {
-                    if vehicle.can_move(&cloned_view) {
-                        vehicle.x -= 1
-                    }
-                }
-                Position::Left => {
-                    if vehicle.can_move(&cloned_view) {
-                        vehicle.x += 1
-                    }
-                }
-                Position::Bottom => {
-                    if vehicle.can_move(&cloned_view) {
-                        vehicle.y -= 1
+#[cfg(not(feature = "gui"))]
+fn run_window(_: &mut Simulation, _: Traffic, _: Option<u64>, _: &str) {
+    exit_with_error("built without the window (the `gui` feature), run with --headless");
+}
+
+// Prints the gridlocks found since the last call, as they happen
+fn log_gridlocks(sim: &Simulation, logged: &mut usize) {
+    for gridlock in &sim.gridlock.gridlocks[*logged..] {
+        let cause = match gridlock.cycle.first() {
+            Some(first) => {
+                let mut cycle: Vec<String> = Vec::new();
+                for (id, stop_line) in gridlock.cycle.iter().zip(&gridlock.stop_lines) {
+                    cycle.push(format!("#{id}"));
+                    if let Some(junction) = stop_line {
+                        cycle.push(format!("stop line of junction {junction}"));
                     }
                 }
+                cycle.push(format!("#{first}"));
+                format!("waiting on each other: {}", cycle.join(" -> "))
             }
+            None => "none of them waiting on each other".to_string(),
+        };
+        println!(
+            "gridlock at tick {}: {} vehicles standing still since tick {}, {}",
+            gridlock.tick, gridlock.vehicles, gridlock.since, cause
+        );
+    }
+    *logged = sim.gridlock.gridlocks.len();
+}
 
-            vehicle.draw(&mut canvas);
-        }
-
-        canvas.present();
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+fn export(sim: &Simulation, dir: &str) {
+    match export_metrics(sim, dir) {
+        Ok(files) => println!("metrics exported to {}", files.join(", ")),
+        Err(err) => eprintln!("error: {err}"),
     }
 }
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {message}");
+    std::process::exit(1);
+}
@@ -0,0 +1,80 @@
+// Command line options:
+//   --headless        run the simulation without opening a window
+//   --config <file>   geometry, timing and window settings (default: config.toml)
+//   --ticks <n>       stop after n ticks (defaults to 3600 when headless)
+//   --seed <n>        seed for the simulation RNG (random when omitted)
+//   --record <file>   save spawns and light changes to a replay file on exit
+//   --replay <file>   play a recorded session back instead of reading the keyboard
+//   --scenario <file> spawn traffic from a scenario file instead of the keyboard
+//   --demand          generate arrivals at the flows of the config's [demand] section
+//   --export <dir>    write metrics as CSV and JSON to dir at the end of the run
+pub struct Options {
+    pub headless: bool,
+    pub config: Option<String>,
+    pub ticks: Option<u64>,
+    pub seed: Option<u64>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub scenario: Option<String>,
+    pub demand: bool,
+    pub export: Option<String>,
+}
+
+impl Options {
+    pub fn parse() -> Self {
+        let mut options = Self {
+            headless: false,
+            config: None,
+            ticks: None,
+            seed: None,
+            record: None,
+            replay: None,
+            scenario: None,
+            demand: false,
+            export: None,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--config" => options.config = Some(parse_value(&arg, args.next())),
+                "--ticks" => options.ticks = Some(parse_value(&arg, args.next())),
+                "--seed" => options.seed = Some(parse_value(&arg, args.next())),
+                "--record" => options.record = Some(parse_value(&arg, args.next())),
+                "--replay" => options.replay = Some(parse_value(&arg, args.next())),
+                "--scenario" => options.scenario = Some(parse_value(&arg, args.next())),
+                "--demand" => options.demand = true,
+                "--export" => options.export = Some(parse_value(&arg, args.next())),
+                _ => exit_with_usage(&format!("unknown argument `{arg}`")),
+            }
+        }
+
+        let sources = [
+            options.replay.is_some(),
+            options.scenario.is_some(),
+            options.demand,
+        ];
+        if sources.iter().filter(|&&given| given).count() > 1 {
+            exit_with_usage("`--replay`, `--scenario` and `--demand` can't be combined");
+        }
+
+        options
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    match value.map(|value| value.parse()) {
+        Some(Ok(value)) => value,
+        _ => exit_with_usage(&format!("`{flag}` expects a value")),
+    }
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("error: {message}");
+    eprintln!(
+        "usage: road_intersection [--headless] [--config <file>] [--ticks <n>] [--seed <n>] \
+         [--record <file>] [--replay <file> | --scenario <file> | --demand] [--export <dir>]"
+    );
+    std::process::exit(2);
+}
@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+
+// Speeds the window can run at, as multiples of real time
+const SPEEDS: [f64; 9] = [0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0];
+const REAL_TIME: usize = 2;
+// simulation steps run between two frames at most at real time, before it
+// falls behind. Faster speeds run as many more.
+const MAX_STEPS_PER_FRAME: f64 = 10.0;
+
+// Turns the time between two frames into a number of fixed simulation steps,
+// at the chosen speed. At high speeds many steps run for every frame drawn.
+pub struct Clock {
+    timestep: Duration,
+    // index into SPEEDS
+    speed: usize,
+    pub paused: bool,
+    // a single step asked for while paused
+    step_once: bool,
+    last_frame: Instant,
+    lag: Duration,
+}
+
+impl Clock {
+    pub fn new(timestep: f64) -> Self {
+        Self {
+            timestep: Duration::from_secs_f64(timestep),
+            speed: REAL_TIME,
+            paused: false,
+            step_once: false,
+            last_frame: Instant::now(),
+            lag: Duration::ZERO,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        SPEEDS[self.speed]
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // Advances exactly one tick with the next frame, only while paused
+    pub fn step(&mut self) {
+        if self.paused {
+            self.step_once = true;
+        }
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    // How many steps to run before drawing the next frame
+    pub fn steps(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = now - self.last_frame;
+        self.last_frame = now;
+
+        if self.paused {
+            self.lag = Duration::ZERO;
+            return std::mem::take(&mut self.step_once) as u32;
+        }
+
+        self.lag += elapsed.mul_f64(self.speed());
+        let steps = self.lag.div_duration_f64(self.timestep) as u32;
+        let max = (MAX_STEPS_PER_FRAME * self.speed()).ceil() as u32;
+        // too far behind to catch up, it drops the rest and slows down
+        if steps > max {
+            self.lag = Duration::ZERO;
+            return max;
+        }
+        self.lag -= self.timestep * steps;
+        steps
+    }
+
+    // For the window title
+    pub fn status(&self) -> String {
+        if self.paused {
+            "paused".to_string()
+        } else {
+            format!("{}×", self.speed())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paused_clock_steps_once_when_asked() {
+        let mut clock = Clock::new(1.0 / 60.0);
+        clock.toggle_pause();
+        assert_eq!(clock.steps(), 0);
+        clock.step();
+        assert_eq!(clock.steps(), 1);
+        assert_eq!(clock.steps(), 0);
+    }
+
+    #[test]
+    fn steps_follow_the_elapsed_time() {
+        let mut clock = Clock::new(1.0 / 60.0);
+        clock.last_frame = Instant::now() - Duration::from_millis(100);
+        // six steps of 1/60 s, a few more when the test runs slowly
+        let steps = clock.steps();
+        assert!(
+            (6..=MAX_STEPS_PER_FRAME as u32).contains(&steps),
+            "{steps} steps"
+        );
+    }
+
+    #[test]
+    fn steps_drop_what_they_cannot_catch_up() {
+        let mut clock = Clock::new(1.0 / 60.0);
+        clock.last_frame = Instant::now() - Duration::from_secs(10);
+        assert_eq!(clock.steps(), MAX_STEPS_PER_FRAME as u32);
+        assert_eq!(clock.lag, Duration::ZERO);
+    }
+}
@@ -0,0 +1,782 @@
+use serde::{Deserialize, Serialize};
+
+use crate::modules::controller::ControllerKind;
+use crate::modules::demand::Headways;
+use crate::modules::lights::Phasing;
+use crate::modules::pedestrians::PEDESTRIAN_SIZE;
+use crate::modules::vehicle::{Direction, Position, VehicleClass};
+
+// Geometry, timing and window settings. Every value has a default, so a config
+// file only needs the ones it changes. Times are in seconds, sizes on screen
+// in pixels, speeds and distances on the road in metres.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub simulation: SimulationConfig,
+    pub window: WindowConfig,
+    pub network: NetworkConfig,
+    pub road: RoadConfig,
+    pub vehicle: VehicleConfig,
+    pub lights: LightsConfig,
+    pub timing: TimingConfig,
+    pub signal: SignalConfig,
+    pub pedestrians: PedestrianConfig,
+    pub demand: DemandConfig,
+}
+
+// The simulation advances in fixed ticks of `timestep` seconds, however fast
+// the window draws, so a run plays out the same on any machine
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    pub timestep: f64,
+    pub pixels_per_metre: f64,
+    // no vehicle moving for this long, while there are some, is a gridlock
+    pub gridlock_time: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+// A grid of junctions, each in an equal share of the window, joined by the
+// roads between them
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoadConfig {
+    // width of one lane, the whole road is twice as wide
+    pub size: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct VehicleConfig {
+    // minimum gap between the front of a vehicle and the rear of the one
+    // ahead of it in its lane
+    pub safety_distance: f64,
+    // time gap kept to the vehicle ahead at speed
+    pub headway: f64,
+    // a vehicle closer than this to the stop line when the light turns amber,
+    // or than it takes to brake, doesn't stop anymore and drives through
+    pub stopping_distance: f64,
+    // a permissive left turn only goes when oncoming traffic is at least this
+    // long away
+    pub left_turn_gap: f64,
+    pub car: ClassConfig,
+    pub truck: ClassConfig,
+    pub bus: ClassConfig,
+    pub motorcycle: ClassConfig,
+    pub bicycle: ClassConfig,
+    pub emergency: ClassConfig,
+    // random spawns only: how often each class comes along
+    pub mix: ClassMix,
+}
+
+// A class given in the config replaces the default one as a whole
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ClassConfig {
+    // on screen, in pixels
+    pub length: u32,
+    pub width: u32,
+    // in m/s
+    pub max_speed: f64,
+    // in m/s², speeding up when nothing is in the way
+    pub acceleration: f64,
+    // in m/s², braking comfortably. It brakes harder when it has to.
+    pub deceleration: f64,
+}
+
+// Weights, relative to each other
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassMix {
+    pub car: f64,
+    pub truck: f64,
+    pub bus: f64,
+    pub motorcycle: f64,
+    pub bicycle: f64,
+    pub emergency: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightsConfig {
+    pub width: i32,
+    pub height: i32,
+    pub margin: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingConfig {
+    pub minimum_light_time: f64,
+    // before an entry can spawn again
+    pub spawn_cool_down: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignalConfig {
+    // which `SignalController` runs the lights
+    pub controller: ControllerKind,
+    pub phasing: Phasing,
+    // concurrent phasing only: a left arrow phase before each green
+    pub protected_left: bool,
+    // actuated control: longest green while others wait, and how long
+    // without a crossing ends the green early
+    pub maximum_green: f64,
+    pub gap: f64,
+    // clearance after every green: amber, then red in all directions
+    pub amber: f64,
+    pub all_red: f64,
+    // the all-red waits for the box to clear this long at most
+    pub max_all_red: f64,
+    // an emergency vehicle closer than `preemption_distance` to its stop
+    // line takes over the signal
+    pub preemption: bool,
+    pub preemption_distance: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PedestrianConfig {
+    pub crosswalk_width: u32,
+    // signal intervals at the start of a green, for pedestrians waiting to
+    // cross beside it
+    pub walk: f64,
+    pub flashing_dont_walk: f64,
+    // walking speed in m/s
+    pub speed: f64,
+    // random and demand traffic: pedestrians per minute, over all crosswalks
+    pub rate: f64,
+}
+
+// Arrivals of `--demand` traffic, and where every vehicle turns when no one
+// says otherwise
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DemandConfig {
+    pub headways: Headways,
+    // shifted headways only: the shortest gap between two arrivals
+    pub min_headway: f64,
+    // vehicles per hour at every entry on each side of the network
+    pub flow: FlowConfig,
+    // vehicles per hour from one entry to one exit, replaces `flow` when
+    // given. Those vehicles follow a route instead of turning at random.
+    pub od: Vec<OdPair>,
+    pub turns: TurnRatios,
+    // flows are multiplied by a factor that varies over time, interpolated
+    // between these points. No points is a factor of 1 throughout.
+    pub profile: Vec<ProfilePoint>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlowConfig {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+// Weights, relative to each other
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TurnRatios {
+    pub left: f64,
+    pub straight: f64,
+    pub right: f64,
+}
+
+// One cell of an origin-destination matrix. Entries and exits are legs on
+// the edge of the network, the junctions only matter in a network of several.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OdPair {
+    pub from: Position,
+    #[serde(default)]
+    pub from_junction: usize,
+    pub to: Position,
+    #[serde(default)]
+    pub to_junction: usize,
+    pub flow: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ProfilePoint {
+    // in seconds since the start
+    pub time: f64,
+    pub factor: f64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            timestep: 1.0 / 60.0,
+            pixels_per_metre: 5.0,
+            gridlock_time: 30.0,
+        }
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 900,
+            height: 700,
+            x: 6060,
+            y: 30,
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            columns: 1,
+            rows: 1,
+        }
+    }
+}
+
+impl Default for RoadConfig {
+    fn default() -> Self {
+        Self { size: 40 }
+    }
+}
+
+impl Default for VehicleConfig {
+    fn default() -> Self {
+        Self {
+            safety_distance: 2.0,
+            headway: 1.0,
+            stopping_distance: 4.0,
+            left_turn_gap: 1.0,
+            car: ClassConfig {
+                length: 25,
+                width: 25,
+                max_speed: 12.0,
+                acceleration: 2.0,
+                deceleration: 3.0,
+            },
+            truck: ClassConfig {
+                length: 60,
+                width: 28,
+                max_speed: 8.5,
+                acceleration: 1.0,
+                deceleration: 2.0,
+            },
+            bus: ClassConfig {
+                length: 70,
+                width: 28,
+                max_speed: 9.5,
+                acceleration: 1.2,
+                deceleration: 2.0,
+            },
+            motorcycle: ClassConfig {
+                length: 16,
+                width: 10,
+                max_speed: 12.0,
+                acceleration: 3.0,
+                deceleration: 4.0,
+            },
+            bicycle: ClassConfig {
+                length: 14,
+                width: 8,
+                max_speed: 5.0,
+                acceleration: 1.0,
+                deceleration: 1.5,
+            },
+            emergency: ClassConfig {
+                length: 32,
+                width: 26,
+                max_speed: 16.0,
+                acceleration: 2.5,
+                deceleration: 4.0,
+            },
+            mix: ClassMix::default(),
+        }
+    }
+}
+
+impl Default for ClassMix {
+    fn default() -> Self {
+        Self {
+            car: 0.75,
+            truck: 0.08,
+            bus: 0.04,
+            motorcycle: 0.08,
+            bicycle: 0.05,
+            emergency: 0.0,
+        }
+    }
+}
+
+impl Default for DemandConfig {
+    fn default() -> Self {
+        Self {
+            headways: Headways::Poisson,
+            min_headway: 1.5,
+            flow: FlowConfig::default(),
+            od: Vec::new(),
+            turns: TurnRatios::default(),
+            profile: Vec::new(),
+        }
+    }
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            top: 150.0,
+            right: 150.0,
+            bottom: 150.0,
+            left: 150.0,
+        }
+    }
+}
+
+impl Default for TurnRatios {
+    fn default() -> Self {
+        Self {
+            left: 1.0,
+            straight: 1.0,
+            right: 1.0,
+        }
+    }
+}
+
+impl SimulationConfig {
+    // Whole ticks in `seconds`
+    pub fn ticks(&self, seconds: f64) -> u64 {
+        (seconds / self.timestep).round() as u64
+    }
+
+    pub fn seconds(&self, ticks: u64) -> f64 {
+        ticks as f64 * self.timestep
+    }
+
+    pub fn ticks_per_minute(&self) -> f64 {
+        60.0 / self.timestep
+    }
+}
+
+impl VehicleConfig {
+    pub fn class(&self, class: &VehicleClass) -> &ClassConfig {
+        match class {
+            VehicleClass::Car => &self.car,
+            VehicleClass::Truck => &self.truck,
+            VehicleClass::Bus => &self.bus,
+            VehicleClass::Motorcycle => &self.motorcycle,
+            VehicleClass::Bicycle => &self.bicycle,
+            VehicleClass::Emergency => &self.emergency,
+        }
+    }
+}
+
+impl PedestrianConfig {
+    // Ticks per pixel walked, pedestrians move a whole pixel at a time.
+    // Rounded down, so they are never slower than `speed`.
+    pub fn pace(&self, simulation: &SimulationConfig) -> u64 {
+        let pixels_per_tick = self.speed * simulation.pixels_per_metre * simulation.timestep;
+        ((1.0 / pixels_per_tick).floor() as u64).max(1)
+    }
+}
+
+impl ClassMix {
+    pub fn weight(&self, class: &VehicleClass) -> f64 {
+        match class {
+            VehicleClass::Car => self.car,
+            VehicleClass::Truck => self.truck,
+            VehicleClass::Bus => self.bus,
+            VehicleClass::Motorcycle => self.motorcycle,
+            VehicleClass::Bicycle => self.bicycle,
+            VehicleClass::Emergency => self.emergency,
+        }
+    }
+}
+
+impl DemandConfig {
+    // The profile's factor `time` seconds into the run
+    pub fn factor(&self, time: f64) -> f64 {
+        let profile = &self.profile;
+        let Some(next) = profile.iter().position(|point| point.time > time) else {
+            return profile.last().map_or(1.0, |point| point.factor);
+        };
+        if next == 0 {
+            return profile[0].factor;
+        }
+
+        let (before, after) = (&profile[next - 1], &profile[next]);
+        let share = (time - before.time) / (after.time - before.time);
+        before.factor + (after.factor - before.factor) * share
+    }
+}
+
+impl FlowConfig {
+    pub fn get(&self, side: &Position) -> f64 {
+        match side {
+            Position::Top => self.top,
+            Position::Right => self.right,
+            Position::Bottom => self.bottom,
+            Position::Left => self.left,
+        }
+    }
+}
+
+impl TurnRatios {
+    pub fn weight(&self, direction: &Direction) -> f64 {
+        match direction {
+            Direction::Left => self.left,
+            Direction::Straight => self.straight,
+            Direction::Right => self.right,
+        }
+    }
+}
+
+impl Default for LightsConfig {
+    fn default() -> Self {
+        Self {
+            width: 22,
+            height: 60,
+            margin: 5,
+        }
+    }
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            minimum_light_time: 2.0,
+            spawn_cool_down: 1.5,
+        }
+    }
+}
+
+impl Default for SignalConfig {
+    fn default() -> Self {
+        Self {
+            controller: ControllerKind::RoundRobin,
+            phasing: Phasing::Split,
+            protected_left: false,
+            maximum_green: 10.0,
+            gap: 0.75,
+            amber: 1.5,
+            all_red: 1.0,
+            max_all_red: 20.0,
+            preemption: true,
+            preemption_distance: 80.0,
+        }
+    }
+}
+
+impl Default for PedestrianConfig {
+    fn default() -> Self {
+        Self {
+            crosswalk_width: 20,
+            walk: 2.0,
+            flashing_dont_walk: 13.0,
+            speed: 1.4,
+            rate: 4.0,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read config `{path}`: {err}"))?;
+
+        let config: Self =
+            toml::from_str(&content).map_err(|err| format!("invalid config `{path}`: {err}"))?;
+        config
+            .validate()
+            .map_err(|err| format!("invalid config `{path}`: {err}"))?;
+
+        Ok(config)
+    }
+
+    // A hash of every setting, defaults included, to tell configs apart.
+    // FNV-1a, so it stays the same from one build to the next.
+    pub fn fingerprint(&self) -> u64 {
+        let settings = serde_json::to_string(self).unwrap();
+        settings.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let road = self.road.size;
+        let vehicle = &self.vehicle;
+
+        // longer steps make vehicles jump past what they should brake for
+        let simulation = &self.simulation;
+        if !(simulation.timestep > 0.0 && simulation.timestep <= 0.1) {
+            return Err("timestep must be above 0 and at most 0.1 seconds".to_string());
+        }
+        if simulation.pixels_per_metre <= 0.0 {
+            return Err("pixels per metre must be positive".to_string());
+        }
+        if simulation.gridlock_time <= 0.0 {
+            return Err("gridlock time must be positive".to_string());
+        }
+
+        for class in VehicleClass::ALL {
+            let name = class.name();
+            let settings = vehicle.class(&class);
+            if settings.length == 0 || settings.width == 0 {
+                return Err(format!("{name} size must be positive"));
+            }
+            // long vehicles stick out of the lane while turning, but they have
+            // to fit its width
+            if road < settings.width {
+                return Err(format!(
+                    "road size {road} is narrower than a {name} ({})",
+                    settings.width
+                ));
+            }
+            if settings.max_speed <= 0.0 {
+                return Err(format!("{name} max speed must be positive"));
+            }
+            if settings.acceleration <= 0.0 || settings.deceleration <= 0.0 {
+                return Err(format!(
+                    "{name} acceleration and deceleration must be positive"
+                ));
+            }
+            if vehicle.mix.weight(&class) < 0.0 {
+                return Err(format!("{name} share of the mix can't be negative"));
+            }
+        }
+        if VehicleClass::ALL
+            .iter()
+            .all(|class| vehicle.mix.weight(class) == 0.0)
+        {
+            return Err("the vehicle mix needs at least one class".to_string());
+        }
+        if vehicle.safety_distance < 0.0 {
+            return Err("vehicle safety distance can't be negative".to_string());
+        }
+        if vehicle.headway < 0.0 {
+            return Err("vehicle headway can't be negative".to_string());
+        }
+        if vehicle.stopping_distance < 0.0 {
+            return Err("vehicle stopping distance can't be negative".to_string());
+        }
+        if vehicle.left_turn_gap < 0.0 {
+            return Err("left turn gap can't be negative".to_string());
+        }
+
+        let network = &self.network;
+        if network.columns == 0 || network.rows == 0 {
+            return Err("the network needs at least one column and one row".to_string());
+        }
+
+        // every intersection and the lights around it have to fit in their
+        // share of the window
+        let lights = &self.lights;
+        if lights.width <= 0 || lights.height <= 0 || lights.margin < 0 {
+            return Err("light sizes must be positive".to_string());
+        }
+        if lights.height < 10 {
+            return Err(format!(
+                "lights {} pixels high can't fit three lamps, they need at least 10",
+                lights.height
+            ));
+        }
+        let needed = 2 * (road as i32 + lights.margin + lights.height.max(lights.width) + 10);
+        let (width, height) = (
+            self.window.width / network.columns,
+            self.window.height / network.rows,
+        );
+        if (width as i32) < needed || (height as i32) < needed {
+            return Err(format!(
+                "a {}x{} window is too small for {}x{} intersections, each needs at least {needed}x{needed}",
+                self.window.width, self.window.height, network.columns, network.rows
+            ));
+        }
+        // vehicles spawn on the edge of the share, the longest has to fit
+        // between there and the stop line with a metre to spare. The stop line
+        // lies past the crosswalk, outside the box.
+        let approach = width.min(height) as i32 / 2
+            - road as i32
+            - (self.pedestrians.crosswalk_width as i32 + 15);
+        let spare = simulation.pixels_per_metre.ceil() as i32;
+        if let Some((class, length)) = VehicleClass::ALL
+            .iter()
+            .map(|class| (class, vehicle.class(class).length as i32))
+            .max_by_key(|(_, length)| *length)
+            && approach < length + spare
+        {
+            return Err(format!(
+                "approach lanes of {approach} pixels are too short for a {} ({length}) in a {}x{} window with {}x{} intersections",
+                class.name(),
+                self.window.width,
+                self.window.height,
+                network.columns,
+                network.rows
+            ));
+        }
+
+        if self.timing.minimum_light_time <= 0.0 {
+            return Err("minimum light time must be positive".to_string());
+        }
+        if self.timing.spawn_cool_down < 0.0 {
+            return Err("spawn cooldown can't be negative".to_string());
+        }
+        if self.signal.maximum_green < self.timing.minimum_light_time {
+            return Err(format!(
+                "maximum green {} is shorter than the minimum light time {}",
+                self.signal.maximum_green, self.timing.minimum_light_time
+            ));
+        }
+        if self.signal.gap <= 0.0 {
+            return Err("signal gap must be positive".to_string());
+        }
+        if self.signal.amber < 0.0 || self.signal.all_red < 0.0 {
+            return Err("amber and all-red can't be negative".to_string());
+        }
+        if self.signal.max_all_red < self.signal.all_red {
+            return Err(format!(
+                "maximum all-red {} is shorter than the all-red {}",
+                self.signal.max_all_red, self.signal.all_red
+            ));
+        }
+        if self.signal.preemption_distance < 0.0 {
+            return Err("preemption distance can't be negative".to_string());
+        }
+        if self.signal.protected_left && self.signal.phasing == Phasing::Split {
+            return Err("protected left turns need concurrent phasing".to_string());
+        }
+
+        let pedestrians = &self.pedestrians;
+        if pedestrians.crosswalk_width < PEDESTRIAN_SIZE {
+            return Err(format!(
+                "crosswalk width {} is narrower than a pedestrian ({PEDESTRIAN_SIZE})",
+                pedestrians.crosswalk_width
+            ));
+        }
+        if pedestrians.walk < 0.0 || pedestrians.flashing_dont_walk < 0.0 {
+            return Err("walk intervals can't be negative".to_string());
+        }
+        if pedestrians.speed <= 0.0 {
+            return Err("pedestrian speed must be positive".to_string());
+        }
+        // someone stepping off the curb as the walk ends is across before the
+        // green does, from one side of the road to the other
+        let crosswalk = (road * 2 + PEDESTRIAN_SIZE) as f64 / simulation.pixels_per_metre;
+        let crossing = crosswalk / pedestrians.speed;
+        if pedestrians.flashing_dont_walk < crossing {
+            return Err(format!(
+                "flashing don't walk of {} s is shorter than the {crossing:.1} s it takes to cross {crosswalk:.1} m at {} m/s",
+                pedestrians.flashing_dont_walk, pedestrians.speed
+            ));
+        }
+        // at most one per tick
+        let ticks_per_minute = simulation.ticks_per_minute();
+        if !(0.0..=ticks_per_minute).contains(&pedestrians.rate) {
+            return Err(format!(
+                "pedestrian rate must be between 0 and {ticks_per_minute} per minute"
+            ));
+        }
+
+        let demand = &self.demand;
+        if demand.min_headway < 0.0 {
+            return Err("minimum headway can't be negative".to_string());
+        }
+        for side in Position::ALL {
+            if demand.flow.get(&side) < 0.0 {
+                return Err(format!("flow from {side:?} can't be negative"));
+            }
+        }
+        if demand.od.iter().any(|pair| pair.flow < 0.0) {
+            return Err("origin-destination flows can't be negative".to_string());
+        }
+        let turns = &demand.turns;
+        if turns.left < 0.0 || turns.straight < 0.0 || turns.right < 0.0 {
+            return Err("turning ratios can't be negative".to_string());
+        }
+        if turns.left + turns.straight + turns.right == 0.0 {
+            return Err("turning ratios need at least one direction".to_string());
+        }
+        let mut time = 0.0;
+        for point in &demand.profile {
+            if point.time < time {
+                return Err(format!(
+                    "demand profile times must go up from 0, {} comes after {time}",
+                    point.time
+                ));
+            }
+            if point.factor < 0.0 {
+                return Err("demand profile factors can't be negative".to_string());
+            }
+            time = point.time;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn demand(profile: &[(f64, f64)]) -> DemandConfig {
+        DemandConfig {
+            profile: profile
+                .iter()
+                .map(|&(time, factor)| ProfilePoint { time, factor })
+                .collect(),
+            ..DemandConfig::default()
+        }
+    }
+
+    #[test]
+    fn factor_is_one_without_a_profile() {
+        assert_eq!(demand(&[]).factor(100.0), 1.0);
+    }
+
+    #[test]
+    fn factor_interpolates_between_points() {
+        let demand = demand(&[(0.0, 1.0), (60.0, 2.0), (120.0, 0.5)]);
+        assert_eq!(demand.factor(0.0), 1.0);
+        assert_eq!(demand.factor(30.0), 1.5);
+        assert_eq!(demand.factor(60.0), 2.0);
+        assert_eq!(demand.factor(90.0), 1.25);
+    }
+
+    #[test]
+    fn factor_holds_outside_the_profile() {
+        let demand = demand(&[(10.0, 0.5), (20.0, 1.5)]);
+        assert_eq!(demand.factor(0.0), 0.5);
+        assert_eq!(demand.factor(500.0), 1.5);
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn approach_lanes_must_hold_the_longest_vehicle() {
+        let mut config = Config::default();
+        config.window.width = 2400;
+        config.window.height = 2400;
+        config.network.columns = 10;
+        config.network.rows = 10;
+        assert!(config.validate().is_err());
+    }
+}
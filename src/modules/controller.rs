@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+use crate::modules::config::Config;
+use crate::modules::lights::{GreenLight, Phase, phase_plan};
+
+// What a signal controller gets to see of the intersection, once per tick
+pub struct Observation {
+    pub phase: Phase,
+    // ticks since the current phase turned green, this tick included
+    pub green_time: usize,
+    // a vehicle is inside the junction box, apart from a permissive left
+    // turn waiting there for a gap under concurrent phasing
+    pub box_occupied: bool,
+    pub approaches: Vec<ApproachState>,
+}
+
+pub struct ApproachState {
+    pub light: GreenLight,
+    // vehicles standing still behind the stop line
+    pub queue: u32,
+    // the ones among them turning left
+    pub left_queue: u32,
+    // ticks since a vehicle last crossed the stop line
+    pub since_crossing: Option<u64>,
+}
+
+impl Observation {
+    pub fn approach(&self, light: &GreenLight) -> &ApproachState {
+        self.approaches
+            .iter()
+            .find(|state| state.light == *light)
+            .unwrap()
+    }
+
+    // Vehicles waiting for `phase`: all of them, or those turning left for a
+    // left arrow
+    pub fn demand(&self, phase: &Phase) -> u32 {
+        phase
+            .lights()
+            .iter()
+            .map(|light| {
+                let approach = self.approach(light);
+                if phase.left_arrow {
+                    approach.left_queue
+                } else {
+                    approach.queue
+                }
+            })
+            .sum()
+    }
+}
+
+pub trait SignalController {
+    // The phase to turn green next, `None` keeps the current one
+    fn next_phase(&mut self, observation: &Observation) -> Option<Phase>;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControllerKind {
+    RoundRobin,
+    Actuated,
+}
+
+pub fn new_controller(config: &Config) -> Box<dyn SignalController> {
+    let plan = phase_plan(&config.signal.phasing, config.signal.protected_left);
+    // controllers count in ticks
+    let ticks = |seconds| config.simulation.ticks(seconds) as usize;
+    match config.signal.controller {
+        ControllerKind::RoundRobin => Box::new(RoundRobin {
+            minimum_green: ticks(config.timing.minimum_light_time),
+            plan,
+        }),
+        ControllerKind::Actuated => Box::new(Actuated {
+            minimum_green: ticks(config.timing.minimum_light_time),
+            maximum_green: ticks(config.signal.maximum_green),
+            gap: ticks(config.signal.gap),
+            plan,
+        }),
+    }
+}
+
+// Where `phase` is in `plan`, the end of it when it isn't there at all
+fn plan_index(plan: &[Phase], phase: &Phase) -> usize {
+    plan.iter()
+        .position(|planned| planned.is_same(phase))
+        .unwrap_or(plan.len() - 1)
+}
+
+// Fixed rotation through the phase plan. A phase stays green for a minimum
+// time and then as long as traffic is still moving through the box.
+pub struct RoundRobin {
+    pub minimum_green: usize,
+    pub plan: Vec<Phase>,
+}
+
+impl SignalController for RoundRobin {
+    fn next_phase(&mut self, observation: &Observation) -> Option<Phase> {
+        if observation.green_time >= self.minimum_green && !observation.box_occupied {
+            let index = plan_index(&self.plan, &observation.phase);
+            return Some(self.plan[(index + 1) % self.plan.len()].clone());
+        }
+
+        None
+    }
+}
+
+// Serves phases by demand. Phases without a waiting vehicle are skipped, the
+// green is extended as long as vehicles keep crossing less than `gap` ticks
+// apart (gap-out) and never beyond `maximum_green` (max-out). With no one
+// waiting elsewhere the green stays where it is. Vehicles still in the box
+// don't hold the green, the all-red waits for them to clear.
+pub struct Actuated {
+    pub minimum_green: usize,
+    pub maximum_green: usize,
+    pub gap: usize,
+    pub plan: Vec<Phase>,
+}
+
+impl SignalController for Actuated {
+    fn next_phase(&mut self, observation: &Observation) -> Option<Phase> {
+        let green_time = observation.green_time;
+        if green_time < self.minimum_green {
+            return None;
+        }
+
+        let phase = &observation.phase;
+        // time without a crossing on any served approach, counted from the
+        // start of the green at most
+        let gap = phase
+            .lights()
+            .iter()
+            .filter_map(|light| observation.approach(light).since_crossing)
+            .min()
+            .map_or(green_time as u64, |since| since.min(green_time as u64));
+        let gapped_out = observation.demand(phase) == 0 && gap >= self.gap as u64;
+        let maxed_out = green_time >= self.maximum_green;
+        if !gapped_out && !maxed_out {
+            return None;
+        }
+
+        // the next phase in the plan with someone waiting
+        let index = plan_index(&self.plan, phase);
+        (1..self.plan.len())
+            .map(|offset| &self.plan[(index + offset) % self.plan.len()])
+            .find(|next| observation.demand(next) > 0)
+            .cloned()
+    }
+}
@@ -0,0 +1,163 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::modules::simulation::{Simulation, random_class, random_direction};
+use crate::modules::vehicle::{Direction, Position, VehicleClass};
+
+// How the time between two arrivals at an entry is spread around its mean
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Headways {
+    // exponential, arrivals independent of each other
+    Poisson,
+    // every arrival the same time after the last
+    Constant,
+    // exponential on top of `min_headway`, no two arrivals closer than that
+    Shifted,
+}
+
+impl Headways {
+    // The next headway in arrivals: 1 is the mean time between two of them at
+    // `rate` arrivals per second
+    fn draw(&self, rng: &mut StdRng, rate: f64, min_headway: f64) -> f64 {
+        let exponential = -(1.0 - rng.gen_range(0.0..1.0f64)).ln();
+        match self {
+            Headways::Poisson => exponential,
+            Headways::Constant => 1.0,
+            Headways::Shifted => {
+                let shift = (min_headway * rate).min(1.0);
+                shift + (1.0 - shift) * exponential
+            }
+        }
+    }
+}
+
+// Arrivals at an entry on the edge of the network: all of them, or those
+// headed for one exit
+struct Stream {
+    junction: usize,
+    start: Position,
+    // the turns of an origin-destination pair, random ones otherwise
+    turns: Option<Vec<Direction>>,
+    // vehicles per hour before the profile's factor
+    flow: f64,
+    // arrivals expected so far, and the count at which the next one is due
+    expected: f64,
+    next: f64,
+    // due but not spawned yet, the entry was still in use. Their class and
+    // turns are drawn when they are due and kept until they enter.
+    waiting: Vec<(VehicleClass, Vec<Direction>)>,
+}
+
+// Generates arrivals from the `[demand]` flows, per entry or per
+// origin-destination pair. Headways are drawn in expected arrivals rather
+// than seconds, and those add up at the rate of the moment, so the flow can
+// change over the run without a long headway drawn at a low rate holding
+// back the peak.
+pub struct DemandGenerator {
+    streams: Vec<Stream>,
+    // arrivals of its own, so the other random choices stay as they are
+    rng: StdRng,
+}
+
+impl DemandGenerator {
+    // Fails when a pair doesn't start at an entry, end at an exit, or has no
+    // way between the two
+    pub fn new(sim: &Simulation, seed: u64) -> Result<Self, String> {
+        let demand = &sim.config.demand;
+        let roads = &sim.roads;
+
+        let mut routes = Vec::new();
+        if demand.od.is_empty() {
+            for lane in roads.entries() {
+                let flow = demand.flow.get(&lane.leg);
+                routes.push((lane.junction, lane.leg.clone(), None, flow));
+            }
+        }
+        for pair in &demand.od {
+            let entry = roads
+                .entries()
+                .into_iter()
+                .find(|lane| lane.junction == pair.from_junction && lane.leg == pair.from)
+                .ok_or(format!(
+                    "no entry into the network at junction {} from {:?}",
+                    pair.from_junction, pair.from
+                ))?;
+            let exit = roads
+                .exits()
+                .into_iter()
+                .find(|lane| lane.junction == pair.to_junction && lane.leg == pair.to)
+                .ok_or(format!(
+                    "no exit out of the network at junction {} to {:?}",
+                    pair.to_junction, pair.to
+                ))?;
+            let turns = roads.plan_route(entry.id, exit.id).ok_or(format!(
+                "no route from {:?} of junction {} to {:?} of junction {}",
+                pair.from, pair.from_junction, pair.to, pair.to_junction
+            ))?;
+            routes.push((entry.junction, entry.leg.clone(), Some(turns), pair.flow));
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(2));
+        let streams = routes
+            .into_iter()
+            .map(|(junction, start, turns, flow)| {
+                let rate = flow * demand.factor(0.0) / 3600.0;
+                Stream {
+                    junction,
+                    start,
+                    turns,
+                    flow,
+                    expected: 0.0,
+                    next: demand.headways.draw(&mut rng, rate, demand.min_headway),
+                    waiting: Vec::new(),
+                }
+            })
+            .collect();
+
+        Ok(Self { streams, rng })
+    }
+
+    // Spawns the arrivals due this tick. One that finds its entry in use
+    // waits for it, like a queue off the edge of the network.
+    pub fn feed(&mut self, sim: &mut Simulation) {
+        let demand = &sim.config.demand;
+        let (headways, min_headway) = (demand.headways.clone(), demand.min_headway);
+        let (turns, mix) = (demand.turns.clone(), sim.config.vehicle.mix.clone());
+        let factor = demand.factor(sim.time());
+        let timestep = sim.config.simulation.timestep;
+
+        for stream in &mut self.streams {
+            let rate = stream.flow * factor / 3600.0;
+            stream.expected += rate * timestep;
+            while stream.expected >= stream.next {
+                let route = match &stream.turns {
+                    Some(route) => route.clone(),
+                    None => vec![random_direction(&mut self.rng, &turns)],
+                };
+                let class = random_class(&mut self.rng, &mix);
+                stream.waiting.push((class, route));
+                stream.next += headways.draw(&mut self.rng, rate, min_headway);
+            }
+
+            let Some((class, route)) = stream.waiting.first() else {
+                continue;
+            };
+            let (junction, start) = (stream.junction, stream.start.clone());
+            if sim.spawn_routed(junction, start, route.clone(), Some(class.clone())) {
+                stream.waiting.remove(0);
+            }
+        }
+
+        sim.maybe_spawn_pedestrian();
+    }
+
+    // Arrivals still waiting to enter the network
+    pub fn waiting(&self) -> u32 {
+        self.streams
+            .iter()
+            .map(|stream| stream.waiting.len() as u32)
+            .sum()
+    }
+}
@@ -0,0 +1,112 @@
+use serde::Serialize;
+
+use crate::modules::geometry::Rect;
+use crate::modules::lights::*;
+use crate::modules::vehicle::{Position, Vehicle};
+
+// Watches the stop line of one approach of a junction: how many vehicles wait behind it and
+// how many cross it.
+#[derive(Serialize)]
+pub struct QueueDetector {
+    pub junction: usize,
+    pub approach: Position,
+    pub light: GreenLight,
+    // queue length at the end of every tick
+    pub queue_lengths: Vec<u32>,
+    // ticks at which a vehicle crossed the stop line
+    pub crossings: Vec<u64>,
+    // how long this approach had the green or amber, and how many crossed meanwhile
+    pub green_ticks: u64,
+    pub green_crossings: u64,
+    #[serde(skip)]
+    ticks_per_minute: f64,
+}
+
+impl QueueDetector {
+    pub fn new(junction: usize, approach: Position, ticks_per_minute: f64) -> Self {
+        Self {
+            junction,
+            light: approach_light(&approach),
+            approach,
+            queue_lengths: Vec::new(),
+            crossings: Vec::new(),
+            green_ticks: 0,
+            green_crossings: 0,
+            ticks_per_minute,
+        }
+    }
+
+    // Records one tick, right after the vehicles moved. `nearby` are the
+    // positions in `vehicles` of the ones at its junction.
+    pub fn update(
+        &mut self,
+        tick: u64,
+        stop_line: &Rect,
+        aspect: &Aspect,
+        vehicles: &mut [Vehicle],
+        nearby: &[usize],
+    ) {
+        // amber still discharges the queue
+        let is_green = *aspect != Aspect::Red;
+        let mut queue = 0;
+
+        for &index in nearby {
+            let vehicle = &mut vehicles[index];
+            if vehicle.trip.origin != self.approach || vehicle.trip.crossing_tick.is_some() {
+                continue;
+            }
+
+            if vehicle.rect().has_intersection(*stop_line) {
+                vehicle.trip.crossing_tick = Some(tick);
+                self.crossings.push(tick);
+                if is_green {
+                    self.green_crossings += 1;
+                }
+            } else if vehicle.stopped {
+                queue += 1;
+            }
+        }
+
+        self.queue_lengths.push(queue);
+        if is_green {
+            self.green_ticks += 1;
+        }
+    }
+
+    // `from Top`, along with the junction in a network of several
+    pub fn label(&self, network: bool) -> String {
+        if network {
+            format!("#{} from {:?}", self.junction, self.approach)
+        } else {
+            format!("from {:?}", self.approach)
+        }
+    }
+
+    pub fn max_queue(&self) -> u32 {
+        self.queue_lengths.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn mean_queue(&self) -> f64 {
+        if self.queue_lengths.is_empty() {
+            return 0.0;
+        }
+        self.queue_lengths.iter().sum::<u32>() as f64 / self.queue_lengths.len() as f64
+    }
+
+    // Vehicles crossing per minute over the whole run
+    pub fn throughput(&self) -> f64 {
+        self.per_minute(self.crossings.len() as u64, self.queue_lengths.len() as u64)
+    }
+
+    // Vehicles crossing per minute of green, how fast the queue empties
+    pub fn discharge_rate(&self) -> f64 {
+        self.per_minute(self.green_crossings, self.green_ticks)
+    }
+
+    fn per_minute(&self, count: u64, ticks: u64) -> f64 {
+        if ticks == 0 {
+            return 0.0;
+        }
+        count as f64 * self.ticks_per_minute / ticks as f64
+    }
+}
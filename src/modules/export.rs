@@ -0,0 +1,255 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::modules::lights::{GreenLight, SignalState};
+use crate::modules::pedestrians::Pedestrian;
+use crate::modules::replay::SimEvent;
+use crate::modules::safety::Violation;
+use crate::modules::simulation::Simulation;
+use crate::modules::vehicle::{Journey, Trip};
+
+#[derive(Serialize)]
+struct LightChange {
+    tick: u64,
+    junction: usize,
+    green_light: GreenLight,
+    concurrent: bool,
+    left_arrow: bool,
+    signal: SignalState,
+}
+
+// Writes trips, journeys through the network, pedestrians, per-tick queue
+// lengths, the light history and safety violations to `dir`, as CSV for
+// spreadsheets and JSON for everything else. Trips of vehicles and pedestrians
+// still on the road are included, without an exit tick. Returns the written
+// files.
+pub fn export_metrics(sim: &Simulation, dir: &str) -> Result<Vec<String>, String> {
+    fs::create_dir_all(dir).map_err(|err| format!("cannot create `{dir}`: {err}"))?;
+
+    let trips: Vec<&Trip> = sim
+        .trips
+        .iter()
+        .chain(sim.vehicles.iter().map(|vehicle| &vehicle.trip))
+        .collect();
+    let journeys: Vec<&Journey> = sim
+        .journeys
+        .iter()
+        .chain(sim.vehicles.iter().map(|vehicle| &vehicle.journey))
+        .collect();
+    let pedestrians: Vec<&Pedestrian> = sim
+        .pedestrians_crossed
+        .iter()
+        .chain(&sim.pedestrians)
+        .collect();
+    let lights: Vec<LightChange> = sim
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            SimEvent::Light {
+                tick,
+                junction,
+                green_light,
+                concurrent,
+                left_arrow,
+                signal,
+            } => Some(LightChange {
+                tick: *tick,
+                junction: *junction,
+                green_light: green_light.clone(),
+                concurrent: *concurrent,
+                left_arrow: *left_arrow,
+                signal: signal.clone(),
+            }),
+            _ => None,
+        })
+        .collect();
+
+    let files = [
+        ("trips.csv", trips_csv(&trips)),
+        ("trips.json", to_json(&trips)),
+        ("journeys.csv", journeys_csv(&journeys)),
+        ("journeys.json", to_json(&journeys)),
+        ("pedestrians.csv", pedestrians_csv(&pedestrians)),
+        ("pedestrians.json", to_json(&pedestrians)),
+        ("queues.csv", queues_csv(sim)),
+        ("queues.json", to_json(&sim.detectors)),
+        ("lights.csv", lights_csv(&lights)),
+        ("lights.json", to_json(&lights)),
+        ("violations.csv", violations_csv(&sim.safety.violations)),
+        ("violations.json", to_json(&sim.safety.violations)),
+    ];
+
+    let mut written = Vec::new();
+    for (name, content) in files {
+        let path = Path::new(dir).join(name);
+        fs::write(&path, content)
+            .map_err(|err| format!("cannot write `{}`: {err}", path.display()))?;
+        written.push(path.display().to_string());
+    }
+
+    Ok(written)
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string_pretty(value).unwrap()
+}
+
+fn trips_csv(trips: &[&Trip]) -> String {
+    let mut csv = String::from(
+        "vehicle_id,class,junction,origin,direction,spawn_tick,first_stop_tick,crossing_tick,\
+         decision_tick,exit_tick,stopped_ticks,stops,distance,travel_time,delay,preempted\n",
+    );
+
+    for trip in trips {
+        writeln!(
+            csv,
+            "{},{},{},{:?},{:?},{},{},{},{},{},{},{},{:.1},{},{},{}",
+            trip.vehicle_id,
+            trip.class.name(),
+            trip.junction,
+            trip.origin,
+            trip.direction,
+            trip.spawn_tick,
+            optional(trip.first_stop_tick),
+            optional(trip.crossing_tick),
+            optional(trip.decision_tick),
+            optional(trip.exit_tick),
+            trip.stopped_ticks,
+            trip.stops,
+            trip.distance,
+            optional(trip.travel_time()),
+            optional(trip.delay()),
+            trip.preempted,
+        )
+        .unwrap();
+    }
+
+    csv
+}
+
+fn journeys_csv(journeys: &[&Journey]) -> String {
+    let mut csv = String::from(
+        "vehicle_id,class,origin,entry_junction,exit_junction,destination,spawn_tick,exit_tick,\
+         junctions,stops,distance,travel_time,delay\n",
+    );
+
+    for journey in journeys {
+        writeln!(
+            csv,
+            "{},{},{:?},{},{},{},{},{},{},{},{:.1},{},{}",
+            journey.vehicle_id,
+            journey.class.name(),
+            journey.origin,
+            journey.entry_junction,
+            journey
+                .exit_junction
+                .map(|junction| junction.to_string())
+                .unwrap_or_default(),
+            journey
+                .destination
+                .as_ref()
+                .map(|leg| format!("{leg:?}"))
+                .unwrap_or_default(),
+            journey.spawn_tick,
+            optional(journey.exit_tick),
+            journey.junctions,
+            journey.stops,
+            journey.distance,
+            optional(journey.travel_time()),
+            optional(journey.delay()),
+        )
+        .unwrap();
+    }
+
+    csv
+}
+
+fn pedestrians_csv(pedestrians: &[&Pedestrian]) -> String {
+    let mut csv = String::from(
+        "pedestrian_id,junction,crosswalk,from,spawn_tick,start_tick,exit_tick,wait\n",
+    );
+    for pedestrian in pedestrians {
+        writeln!(
+            csv,
+            "{},{},{:?},{:?},{},{},{},{}",
+            pedestrian.id,
+            pedestrian.junction,
+            pedestrian.crosswalk,
+            pedestrian.from,
+            pedestrian.spawn_tick,
+            optional(pedestrian.start_tick),
+            optional(pedestrian.exit_tick),
+            optional(pedestrian.wait())
+        )
+        .unwrap();
+    }
+
+    csv
+}
+
+// One row per tick, one column per approach of every junction
+fn queues_csv(sim: &Simulation) -> String {
+    let mut csv = String::from("tick");
+    for detector in &sim.detectors {
+        if sim.junctions.len() > 1 {
+            write!(csv, ",{}:{:?}", detector.junction, detector.approach).unwrap();
+        } else {
+            write!(csv, ",{:?}", detector.approach).unwrap();
+        }
+    }
+    csv.push('\n');
+
+    for tick in 0..sim.tick as usize {
+        write!(csv, "{tick}").unwrap();
+        for detector in &sim.detectors {
+            write!(csv, ",{}", detector.queue_lengths[tick]).unwrap();
+        }
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn lights_csv(lights: &[LightChange]) -> String {
+    let mut csv = String::from("tick,junction,green_light,concurrent,left_arrow,signal\n");
+    for light in lights {
+        writeln!(
+            csv,
+            "{},{},{:?},{},{},{:?}",
+            light.tick,
+            light.junction,
+            light.green_light,
+            light.concurrent,
+            light.left_arrow,
+            light.signal
+        )
+        .unwrap();
+    }
+
+    csv
+}
+
+fn violations_csv(violations: &[Violation]) -> String {
+    let mut csv = String::from("tick,kind,junction,vehicle_id,other_id\n");
+    for violation in violations {
+        writeln!(
+            csv,
+            "{},{:?},{},{},{}",
+            violation.tick,
+            violation.kind,
+            violation.junction,
+            violation.vehicle_id,
+            optional(violation.other_id)
+        )
+        .unwrap();
+    }
+
+    csv
+}
+
+fn optional(value: Option<u64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
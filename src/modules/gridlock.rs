@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::modules::geometry::Rect;
+use crate::modules::vehicle::{Blocker, Vehicle};
+
+// A standstill: vehicles on the road and none of them moved for
+// `gridlock_time`. When they are waiting on each other in a circle, `cycle`
+// lists them, each one held back by the next and the last by the first.
+// `stop_lines` has the junction for those held at its red stop line, the next
+// one keeping the signal from changing.
+#[derive(Serialize, Clone, Debug)]
+pub struct Gridlock {
+    pub tick: u64,
+    // the last tick a vehicle moved
+    pub since: u64,
+    pub vehicles: usize,
+    pub cycle: Vec<u64>,
+    pub stop_lines: Vec<Option<usize>>,
+    // when a vehicle moved again
+    pub end_tick: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct GridlockDetector {
+    pub gridlocks: Vec<Gridlock>,
+    last_moved: u64,
+}
+
+impl GridlockDetector {
+    // Records one tick, right after the vehicles moved. `limit` is in ticks.
+    pub fn update(&mut self, tick: u64, vehicles: &[Vehicle], boxes: &[Rect], limit: u64) {
+        if vehicles.iter().all(|vehicle| vehicle.stopped) && !vehicles.is_empty() {
+            if self.current().is_none() && tick - self.last_moved >= limit {
+                let (cycle, stop_lines) = wait_cycle(vehicles, boxes);
+                self.gridlocks.push(Gridlock {
+                    tick,
+                    since: self.last_moved,
+                    vehicles: vehicles.len(),
+                    cycle,
+                    stop_lines,
+                    end_tick: None,
+                });
+            }
+            return;
+        }
+
+        self.last_moved = tick;
+        if let Some(gridlock) = self.gridlocks.last_mut()
+            && gridlock.end_tick.is_none()
+        {
+            gridlock.end_tick = Some(tick);
+        }
+    }
+
+    // The gridlock going on right now, if any
+    pub fn current(&self) -> Option<&Gridlock> {
+        self.gridlocks
+            .last()
+            .filter(|gridlock| gridlock.end_tick.is_none())
+    }
+}
+
+// Follows from every vehicle what it's waiting for until the chain comes back
+// on itself: another vehicle, or, at a red stop line, the vehicles in the box
+// of its junction, which keep the signal from changing. The first circle found,
+// starting from the oldest vehicle, or nothing when every chain ends at a
+// pedestrian or a free road. Along with it, for each vehicle of the circle, the
+// junction at whose stop line it waits, if it does.
+fn wait_cycle(vehicles: &[Vehicle], boxes: &[Rect]) -> (Vec<u64>, Vec<Option<usize>>) {
+    // vehicles in a box or on their way through it, per junction
+    let holding = |junction: usize| -> Vec<u64> {
+        let area = boxes[junction];
+        vehicles
+            .iter()
+            .filter(|vehicle| {
+                vehicle.junction == junction
+                    && (area.has_intersection(vehicle.rect())
+                        || vehicle.trip.crossing_tick.is_some() && !vehicle.is_past(&area))
+            })
+            .map(|vehicle| vehicle.id)
+            .collect()
+    };
+    let waits_for: HashMap<u64, (Option<usize>, Vec<u64>)> = vehicles
+        .iter()
+        .filter_map(|vehicle| match vehicle.blocker {
+            Some(Blocker::Vehicle(other)) => Some((vehicle.id, (None, vec![other]))),
+            Some(Blocker::StopLine(_)) => Some((
+                vehicle.id,
+                (Some(vehicle.junction), holding(vehicle.junction)),
+            )),
+            _ => None,
+        })
+        .collect();
+
+    // vehicles known not to lead back into a circle
+    let mut done = HashSet::new();
+    for vehicle in vehicles {
+        let mut path = Vec::new();
+        if let Some(cycle) = search(vehicle.id, &waits_for, &mut done, &mut path) {
+            let stop_lines = cycle
+                .iter()
+                .map(|id| waits_for.get(id).and_then(|(junction, _)| *junction))
+                .collect();
+            return (cycle, stop_lines);
+        }
+    }
+
+    (Vec::new(), Vec::new())
+}
+
+// Depth first from `id`, with `path` the chain that led there
+fn search(
+    id: u64,
+    waits_for: &HashMap<u64, (Option<usize>, Vec<u64>)>,
+    done: &mut HashSet<u64>,
+    path: &mut Vec<u64>,
+) -> Option<Vec<u64>> {
+    if let Some(start) = path.iter().position(|&other| other == id) {
+        return Some(path[start..].to_vec());
+    }
+    if done.contains(&id) {
+        return None;
+    }
+
+    path.push(id);
+    if let Some((_, next)) = waits_for.get(&id) {
+        for &next in next {
+            if let Some(cycle) = search(next, waits_for, done, path) {
+                return Some(cycle);
+            }
+        }
+    }
+    path.pop();
+    done.insert(id);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::config::Config;
+    use crate::modules::lights::approach_light;
+    use crate::modules::simulation::Simulation;
+    use crate::modules::vehicle::{Direction, Position, VehicleClass};
+
+    fn vehicle(sim: &Simulation, id: u64, leg: Position) -> Vehicle {
+        let lane = sim.roads.approach(0, &leg).id;
+        let mut vehicle = Vehicle::new(sim, lane, Direction::Straight, VehicleClass::Car);
+        vehicle.id = id;
+        vehicle
+    }
+
+    #[test]
+    fn finds_vehicles_waiting_on_each_other() {
+        let sim = Simulation::new(&Config::default(), 1);
+        let mut vehicles: Vec<Vehicle> = Position::ALL
+            .into_iter()
+            .enumerate()
+            .map(|(id, leg)| vehicle(&sim, id as u64, leg))
+            .collect();
+        // #0 waits on nothing, #1 -> #2 -> #3 -> #1
+        vehicles[1].blocker = Some(Blocker::Vehicle(2));
+        vehicles[2].blocker = Some(Blocker::Vehicle(3));
+        vehicles[3].blocker = Some(Blocker::Vehicle(1));
+
+        let (cycle, stop_lines) = wait_cycle(&vehicles, &sim.roads.boxes);
+        assert_eq!(cycle, vec![1, 2, 3]);
+        assert_eq!(stop_lines, vec![None; 3]);
+    }
+
+    #[test]
+    fn no_cycle_when_chains_end() {
+        let sim = Simulation::new(&Config::default(), 1);
+        let mut vehicles = vec![
+            vehicle(&sim, 0, Position::Top),
+            vehicle(&sim, 1, Position::Top),
+        ];
+        vehicles[1].blocker = Some(Blocker::Vehicle(0));
+        vehicles[0].blocker = Some(Blocker::Pedestrian(0));
+
+        assert_eq!(
+            wait_cycle(&vehicles, &sim.roads.boxes),
+            (Vec::new(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn follows_a_red_stop_line_to_the_vehicles_in_the_box() {
+        let sim = Simulation::new(&Config::default(), 1);
+        let mut vehicles = vec![
+            vehicle(&sim, 0, Position::Left),
+            vehicle(&sim, 1, Position::Top),
+        ];
+        // #1 stands in the box, waiting behind #0, which is held at its stop
+        // line while the box isn't clear
+        let area = sim.roads.boxes[0];
+        vehicles[1].x = area.x() as f64;
+        vehicles[1].y = area.y() as f64;
+        vehicles[1].blocker = Some(Blocker::Vehicle(0));
+        vehicles[0].blocker = Some(Blocker::StopLine(approach_light(&Position::Left)));
+
+        let (cycle, stop_lines) = wait_cycle(&vehicles, &sim.roads.boxes);
+        assert_eq!(cycle, vec![0, 1]);
+        assert_eq!(stop_lines, vec![Some(0), None]);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::modules::config::Config;
+use crate::modules::controller::{SignalController, new_controller};
+use crate::modules::geometry::{Point, Rect};
+use crate::modules::lights::*;
+use crate::modules::pedestrians::WalkSignal;
+use crate::modules::vehicle::{CoolDown, Position, Vehicle, VehicleClass};
+
+// One intersection of the network and its signal. It owns a cell of the world,
+// `area`, with the crossing in the middle. Its lanes and movements are part of
+// the `RoadGraph`.
+pub struct Junction {
+    pub index: usize,
+    pub area: Rect,
+    pub center: Point,
+    // the phase that has, or last had, the green
+    pub phase: Phase,
+    pub signal: SignalState,
+    // picked by the controller, turns green once the clearance is over
+    pub next_phase: Option<Phase>,
+    // tick of the last signal change
+    pub light_changed_at: u64,
+    pub controller: Box<dyn SignalController>,
+    // entries on the edge of the network only
+    pub cool_downs: CoolDown,
+    // the class drawn for an arrival that didn't fit on its entry yet, kept
+    // for the next try there
+    pub held_classes: Vec<(Position, VehicleClass)>,
+    // one per leg, between the stop line and the intersection
+    pub crosswalks: [(Position, Rect); 4],
+    // crosswalks showing walk during the current green
+    pub walking: Vec<Position>,
+    // the emergency vehicle the signal is preempted for
+    pub preempted_by: Option<u64>,
+}
+
+impl Junction {
+    // The junction in `column` and `row` of the network grid, which splits
+    // the window in equal cells
+    pub fn new(config: &Config, index: usize, column: u32, row: u32) -> Self {
+        let network = &config.network;
+        let width = config.window.width / network.columns;
+        let height = config.window.height / network.rows;
+        let area = Rect::new(
+            (column * width) as i32,
+            (row * height) as i32,
+            width,
+            height,
+        );
+        let center = Point::new(
+            area.x() + (width / 2) as i32,
+            area.y() + (height / 2) as i32,
+        );
+        let crosswalks = Self::get_crosswalks(
+            &center,
+            config.road.size,
+            config.pedestrians.crosswalk_width,
+        );
+
+        Self {
+            index,
+            area,
+            center,
+            // the last phase of the plan, so that the first switch goes to
+            // the first one
+            phase: phase_plan(&config.signal.phasing, config.signal.protected_left)
+                .pop()
+                .unwrap(),
+            signal: SignalState::Green,
+            next_phase: None,
+            light_changed_at: 0,
+            controller: new_controller(config),
+            cool_downs: CoolDown {
+                top: 0,
+                right: 0,
+                bottom: 0,
+                left: 0,
+            },
+            crosswalks,
+            walking: Vec::new(),
+            held_classes: Vec::new(),
+            preempted_by: None,
+        }
+    }
+
+    // The vehicle drove out of the area, through the side it was heading to
+    pub fn has_left(&self, vehicle: &Vehicle) -> bool {
+        vehicle.is_past(&self.area)
+    }
+
+    fn get_crosswalks(center: &Point, road_size: u32, width: u32) -> [(Position, Rect); 4] {
+        let (road, w) = (road_size as i32, width as i32);
+        [
+            (
+                Position::Top,
+                Rect::new(center.x - road, center.y - road - w, road_size * 2, width),
+            ),
+            (
+                Position::Right,
+                Rect::new(center.x + road, center.y - road, width, road_size * 2),
+            ),
+            (
+                Position::Bottom,
+                Rect::new(center.x - road, center.y + road, road_size * 2, width),
+            ),
+            (
+                Position::Left,
+                Rect::new(center.x - road - w, center.y - road, width, road_size * 2),
+            ),
+        ]
+    }
+
+    // Ticks since the signal last changed
+    pub fn light_time(&self, tick: u64) -> usize {
+        (tick - self.light_changed_at) as usize
+    }
+
+    pub fn aspect(&self, light: &GreenLight) -> Aspect {
+        aspect(&self.phase, &self.signal, light)
+    }
+
+    pub fn crosswalk(&self, leg: &Position) -> Rect {
+        self.crosswalks
+            .iter()
+            .find(|(crosswalk, _)| crosswalk == leg)
+            .map(|(_, rect)| *rect)
+            .unwrap()
+    }
+
+    pub fn walk_signal(&self, crosswalk: &Position, tick: u64, config: &Config) -> WalkSignal {
+        if self.signal != SignalState::Green || !self.walking.contains(crosswalk) {
+            return WalkSignal::DontWalk;
+        }
+
+        let (pedestrians, simulation) = (&config.pedestrians, &config.simulation);
+        let time = self.light_time(tick) as u64;
+        if time < simulation.ticks(pedestrians.walk) {
+            WalkSignal::Walk
+        } else if time < simulation.ticks(pedestrians.walk + pedestrians.flashing_dont_walk) {
+            WalkSignal::FlashingDontWalk
+        } else {
+            WalkSignal::DontWalk
+        }
+    }
+}
+
+// What the head of `light` shows while `phase` is in state `signal`
+pub fn aspect(phase: &Phase, signal: &SignalState, light: &GreenLight) -> Aspect {
+    if !phase.serves(light) {
+        return Aspect::Red;
+    }
+
+    match signal {
+        SignalState::Green if phase.left_arrow => Aspect::LeftArrow,
+        SignalState::Green => Aspect::Green,
+        SignalState::Amber => Aspect::Amber,
+        SignalState::AllRed => Aspect::Red,
+    }
+}
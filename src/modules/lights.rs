@@ -1,11 +1,8 @@
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use serde::{Deserialize, Serialize};
 
-use crate::modules::view::*;
+use crate::modules::vehicle::Position;
 
-#[derive(Eq, PartialEq, Clone, Debug)]
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum GreenLight {
     TopLeft,
     TopRight,
@@ -13,75 +10,134 @@ pub enum GreenLight {
     BottomLeft,
 }
 
-pub fn draw_lights(canvas: &mut Canvas<Window>, view: &View) {
-    let road_size = view.road_size as i32;
-    let light_w = view.light_width;
-    let light_h = view.light_height;
-    let margin = view.lights_margin;
+impl GreenLight {
+    // The next light in the clockwise rotation
+    pub fn next(&self) -> GreenLight {
+        match self {
+            GreenLight::TopLeft => GreenLight::TopRight,
+            GreenLight::TopRight => GreenLight::BottomRight,
+            GreenLight::BottomRight => GreenLight::BottomLeft,
+            GreenLight::BottomLeft => GreenLight::TopLeft,
+        }
+    }
+
+    // The light of the approach across the intersection
+    pub fn opposite(&self) -> GreenLight {
+        self.next().next()
+    }
+}
+
+// How approaches share the green
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phasing {
+    // one approach at a time, every movement protected
+    Split,
+    // opposite approaches together, left turns yield to oncoming traffic
+    Concurrent,
+}
+
+// The movements that have the green together. Named after one light, with
+// concurrent phasing the opposite light is green too. A left arrow phase only
+// lets left turns go.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Phase {
+    pub light: GreenLight,
+    pub concurrent: bool,
+    pub left_arrow: bool,
+}
+
+impl Phase {
+    pub fn split(light: GreenLight) -> Self {
+        Self {
+            light,
+            concurrent: false,
+            left_arrow: false,
+        }
+    }
+
+    pub fn serves(&self, light: &GreenLight) -> bool {
+        *light == self.light || (self.concurrent && *light == self.light.opposite())
+    }
+
+    pub fn lights(&self) -> Vec<GreenLight> {
+        if self.concurrent {
+            vec![self.light.clone(), self.light.opposite()]
+        } else {
+            vec![self.light.clone()]
+        }
+    }
 
-    // box dimensions
-    let box_w = light_w + 10;
-    let box_h = light_h + 10;
+    // Same movements, whichever of the two lights a concurrent phase is named after
+    pub fn is_same(&self, other: &Phase) -> bool {
+        self.concurrent == other.concurrent
+            && self.left_arrow == other.left_arrow
+            && self.serves(&other.light)
+    }
+}
 
-    let lights = [
-        (
+// The order phases come in: clockwise for split phasing, north-south then
+// east-west for concurrent phasing, each preceded by its left arrow when
+// left turns are protected
+pub fn phase_plan(phasing: &Phasing, protected_left: bool) -> Vec<Phase> {
+    match phasing {
+        Phasing::Split => [
             GreenLight::TopLeft,
-            Rect::new(
-                view.center.x - road_size - box_w - margin,
-                view.center.y - road_size - box_h - margin,
-                box_w as u32,
-                box_h as u32,
-            ),
-        ),
-        (
             GreenLight::TopRight,
-            Rect::new(
-                view.center.x + road_size + margin,
-                view.center.y - road_size - box_h - margin,
-                box_w as u32,
-                box_h as u32,
-            ),
-        ),
-        (
             GreenLight::BottomRight,
-            Rect::new(
-                view.center.x + road_size + margin,
-                view.center.y + road_size + margin,
-                box_w as u32,
-                box_h as u32,
-            ),
-        ),
-        (
             GreenLight::BottomLeft,
-            Rect::new(
-                view.center.x - road_size - box_w - margin,
-                view.center.y + road_size + margin,
-                box_w as u32,
-                box_h as u32,
-            ),
-        ),
-    ];
-
-    for (position, box_rect) in lights {
-        // Draw box background
-        canvas.set_draw_color(Color::RGB(30, 30, 30)); // dark gray box
-        canvas.fill_rect(box_rect).unwrap();
+        ]
+        .map(Phase::split)
+        .into(),
+        Phasing::Concurrent => {
+            let mut plan = Vec::new();
+            for light in [GreenLight::TopLeft, GreenLight::TopRight] {
+                if protected_left {
+                    plan.push(Phase {
+                        light: light.clone(),
+                        concurrent: true,
+                        left_arrow: true,
+                    });
+                }
+                plan.push(Phase {
+                    light,
+                    concurrent: true,
+                    left_arrow: false,
+                });
+            }
+            plan
+        }
+    }
+}
 
-        let light_rect = Rect::new(
-            box_rect.x() + 5,
-            box_rect.y() + 5,
-            light_w as u32,
-            light_h as u32,
-        );
+// The light that controls traffic coming from `approach`
+pub fn approach_light(approach: &Position) -> GreenLight {
+    match approach {
+        Position::Top => GreenLight::TopLeft,
+        Position::Right => GreenLight::TopRight,
+        Position::Bottom => GreenLight::BottomRight,
+        Position::Left => GreenLight::BottomLeft,
+    }
+}
 
-        let is_green = view.green_light == position;
-        let color = if is_green {
-            Color::RGB(0, 255, 0)
-        } else {
-            Color::RGB(180, 0, 0)
-        };
+// Where the signal is in its cycle. The green light goes amber when the
+// controller picks the next one, then every light is red until the
+// intersection had time to clear.
+#[derive(Eq, PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalState {
+    #[default]
+    Green,
+    Amber,
+    AllRed,
+}
 
-        canvas.set_draw_color(color);
-        canvas.fill_rect(light_rect).unwrap();
-    }
+// What a single signal head shows
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum Aspect {
+    Green,
+    // green for left turns only
+    LeftArrow,
+    Amber,
+    Red,
 }
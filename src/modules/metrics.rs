@@ -0,0 +1,302 @@
+use crate::modules::detectors::QueueDetector;
+use crate::modules::pedestrians::Pedestrian;
+use crate::modules::safety::{Violation, ViolationKind};
+use crate::modules::vehicle::{Direction, Journey, Position, Trip, VehicleClass};
+
+// Travel time, delay and stops over a group of finished trips, in ticks
+pub struct TripSummary {
+    pub count: usize,
+    pub mean_travel_time: f64,
+    pub p50_travel_time: u64,
+    pub p85_travel_time: u64,
+    pub p95_travel_time: u64,
+    pub mean_delay: f64,
+    pub mean_stops: f64,
+}
+
+impl TripSummary {
+    // Trips that haven't left the road yet are ignored
+    pub fn new<'a>(trips: impl Iterator<Item = &'a Trip>) -> Option<Self> {
+        Self::summarize(
+            trips
+                .filter_map(|trip| Some((trip.travel_time()?, trip.delay()?, trip.stops)))
+                .collect(),
+        )
+    }
+
+    // The same over whole journeys through the network
+    pub fn of_journeys<'a>(journeys: impl Iterator<Item = &'a Journey>) -> Option<Self> {
+        Self::summarize(
+            journeys
+                .filter_map(|journey| {
+                    Some((journey.travel_time()?, journey.delay()?, journey.stops))
+                })
+                .collect(),
+        )
+    }
+
+    // From the travel time, delay and stops of each finished trip
+    fn summarize(finished: Vec<(u64, u64, u32)>) -> Option<Self> {
+        if finished.is_empty() {
+            return None;
+        }
+
+        let mut travel_times: Vec<u64> = finished.iter().map(|(time, _, _)| *time).collect();
+        travel_times.sort_unstable();
+
+        let count = finished.len();
+        let delay: u64 = finished.iter().map(|(_, delay, _)| delay).sum();
+        let stops: u32 = finished.iter().map(|(_, _, stops)| stops).sum();
+
+        Some(Self {
+            count,
+            mean_travel_time: travel_times.iter().sum::<u64>() as f64 / count as f64,
+            p50_travel_time: percentile(&travel_times, 50.0),
+            p85_travel_time: percentile(&travel_times, 85.0),
+            p95_travel_time: percentile(&travel_times, 95.0),
+            mean_delay: delay as f64 / count as f64,
+            mean_stops: stops as f64 / count as f64,
+        })
+    }
+}
+
+// Nearest-rank percentile of an ascending, non-empty slice
+pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+// Times in seconds, converted from ticks of `timestep`
+pub fn print_trip_report(trips: &[Trip], timestep: f64) {
+    let directions = [Direction::Left, Direction::Straight, Direction::Right];
+
+    println!(
+        "{:<16}{:>6}{:>12}{:>7}{:>7}{:>7}{:>12}{:>8}",
+        "trips (s)", "count", "mean travel", "p50", "p85", "p95", "mean delay", "stops"
+    );
+    print_row("all", TripSummary::new(trips.iter()), timestep);
+    for approach in Position::ALL {
+        let label = format!("from {approach:?}");
+        print_row(
+            &label,
+            TripSummary::new(trips.iter().filter(|trip| trip.origin == approach)),
+            timestep,
+        );
+    }
+    for direction in directions {
+        let label = format!("turning {direction:?}");
+        print_row(
+            &label,
+            TripSummary::new(trips.iter().filter(|trip| trip.direction == direction)),
+            timestep,
+        );
+    }
+    // only the classes that came along
+    for class in VehicleClass::ALL {
+        if trips.iter().any(|trip| trip.class == class) {
+            print_row(
+                class.name(),
+                TripSummary::new(trips.iter().filter(|trip| trip.class == class)),
+                timestep,
+            );
+        }
+    }
+
+    let junctions = trips.iter().map(|trip| trip.junction).max().unwrap_or(0) + 1;
+    if junctions > 1 {
+        for junction in 0..junctions {
+            let label = format!("at junction #{junction}");
+            print_row(
+                &label,
+                TripSummary::new(trips.iter().filter(|trip| trip.junction == junction)),
+                timestep,
+            );
+        }
+    }
+}
+
+// Through the whole network, from the entry to the exit
+pub fn print_journey_report(journeys: &[Journey], timestep: f64) {
+    println!(
+        "{:<16}{:>6}{:>12}{:>7}{:>7}{:>7}{:>12}{:>8}",
+        "journeys (s)", "count", "mean travel", "p50", "p85", "p95", "mean delay", "stops"
+    );
+    print_row("all", TripSummary::of_journeys(journeys.iter()), timestep);
+    for approach in Position::ALL {
+        let label = format!("from {approach:?}");
+        print_row(
+            &label,
+            TripSummary::of_journeys(journeys.iter().filter(|journey| journey.origin == approach)),
+            timestep,
+        );
+    }
+
+    let junctions: u32 = journeys.iter().map(|journey| journey.junctions).sum();
+    println!(
+        "{:.2} junctions per journey",
+        junctions as f64 / journeys.len() as f64
+    );
+}
+
+// Journeys per origin-destination pair, those that left the network only
+pub fn print_od_report(journeys: &[Journey], timestep: f64) {
+    println!(
+        "{:<16}{:>6}{:>12}{:>7}{:>7}{:>7}{:>12}{:>8}",
+        "od (s)", "count", "mean travel", "p50", "p85", "p95", "mean delay", "stops"
+    );
+
+    let mut pairs: Vec<(usize, &Position, usize, &Position)> = Vec::new();
+    for journey in journeys {
+        if let (Some(exit), Some(destination)) = (journey.exit_junction, &journey.destination) {
+            let pair = (journey.entry_junction, &journey.origin, exit, destination);
+            if !pairs.contains(&pair) {
+                pairs.push(pair);
+            }
+        }
+    }
+    pairs.sort_by_key(|(entry, origin, exit, destination)| {
+        (*entry, leg_order(origin), *exit, leg_order(destination))
+    });
+
+    let mut origin = None;
+    for (entry, from, exit, to) in pairs {
+        if origin != Some((entry, from)) {
+            println!("from {from:?} #{entry}");
+            origin = Some((entry, from));
+        }
+        let label = format!("  to {to:?} #{exit}");
+        print_row(
+            &label,
+            TripSummary::of_journeys(journeys.iter().filter(|journey| {
+                journey.entry_junction == entry
+                    && journey.origin == *from
+                    && journey.exit_junction == Some(exit)
+                    && journey.destination.as_ref() == Some(to)
+            })),
+            timestep,
+        );
+    }
+}
+
+fn leg_order(leg: &Position) -> usize {
+    Position::ALL.iter().position(|other| other == leg).unwrap()
+}
+
+fn print_row(label: &str, summary: Option<TripSummary>, timestep: f64) {
+    match summary {
+        Some(s) => println!(
+            "{:<16}{:>6}{:>12.1}{:>7.1}{:>7.1}{:>7.1}{:>12.1}{:>8.2}",
+            label,
+            s.count,
+            s.mean_travel_time * timestep,
+            s.p50_travel_time as f64 * timestep,
+            s.p85_travel_time as f64 * timestep,
+            s.p95_travel_time as f64 * timestep,
+            s.mean_delay * timestep,
+            s.mean_stops
+        ),
+        None => println!("{label:<16}{:>6}", 0),
+    }
+}
+
+// Emergency vehicle trips, split by whether the signal was preempted for them.
+// A run with preemption off gives the other half of the comparison.
+pub fn print_emergency_report(trips: &[Trip], timestep: f64) {
+    println!(
+        "{:<16}{:>6}{:>12}{:>7}{:>7}{:>7}{:>12}{:>8}",
+        "emergency (s)", "count", "mean travel", "p50", "p85", "p95", "mean delay", "stops"
+    );
+    for (label, preempted) in [("preempted", true), ("not preempted", false)] {
+        print_row(
+            label,
+            TripSummary::new(trips.iter().filter(|trip| {
+                trip.class == VehicleClass::Emergency && trip.preempted == preempted
+            })),
+            timestep,
+        );
+    }
+}
+
+pub fn print_queue_report(detectors: &[QueueDetector]) {
+    println!(
+        "{:<16}{:>10}{:>12}{:>10}{:>12}{:>14}",
+        "queues", "max queue", "mean queue", "veh/min", "green time", "discharge/min"
+    );
+    let network = detectors.iter().any(|detector| detector.junction > 0);
+    for detector in detectors {
+        let ticks = detector.queue_lengths.len().max(1) as f64;
+        println!(
+            "{:<16}{:>10}{:>12.2}{:>10.2}{:>11.0}%{:>14.2}",
+            detector.label(network),
+            detector.max_queue(),
+            detector.mean_queue(),
+            detector.throughput(),
+            detector.green_ticks as f64 * 100.0 / ticks,
+            detector.discharge_rate()
+        );
+    }
+}
+
+// Waits at the curb and crossing times, in seconds
+pub fn print_pedestrian_report(pedestrians: &[Pedestrian], timestep: f64) {
+    let mut waits: Vec<u64> = pedestrians
+        .iter()
+        .filter_map(|pedestrian| pedestrian.wait())
+        .collect();
+    waits.sort_unstable();
+    let crossing: u64 = pedestrians
+        .iter()
+        .filter_map(|pedestrian| pedestrian.crossing_time())
+        .sum();
+    let count = waits.len();
+
+    println!(
+        "{:<16}{:>6}{:>11}{:>7}{:>7}{:>15}",
+        "pedestrians (s)", "count", "mean wait", "p85", "max", "mean crossing"
+    );
+    println!(
+        "{:<16}{:>6}{:>11.1}{:>7.1}{:>7.1}{:>15.1}",
+        "all",
+        count,
+        waits.iter().sum::<u64>() as f64 * timestep / count as f64,
+        percentile(&waits, 85.0) as f64 * timestep,
+        waits[count - 1] as f64 * timestep,
+        crossing as f64 * timestep / count as f64
+    );
+}
+
+// How often each safety invariant was broken, and when first
+pub fn print_safety_report(violations: &[Violation]) {
+    println!("{:<16}{:>6}{:>12}", "safety", "count", "first tick");
+    for kind in ViolationKind::ALL {
+        let ticks: Vec<u64> = violations
+            .iter()
+            .filter(|violation| violation.kind == kind)
+            .map(|violation| violation.tick)
+            .collect();
+        match ticks.first() {
+            Some(first) => println!("{:<16}{:>6}{:>12}", kind.name(), ticks.len(), first),
+            None => println!("{:<16}{:>6}", kind.name(), 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_takes_the_nearest_rank() {
+        let sorted = [10, 20, 30, 40, 50, 60, 70, 80, 90, 100];
+        assert_eq!(percentile(&sorted, 50.0), 50);
+        assert_eq!(percentile(&sorted, 85.0), 90);
+        assert_eq!(percentile(&sorted, 95.0), 100);
+        assert_eq!(percentile(&sorted, 100.0), 100);
+    }
+
+    #[test]
+    fn percentile_stays_in_bounds() {
+        assert_eq!(percentile(&[7], 50.0), 7);
+        assert_eq!(percentile(&[1, 2, 3], 0.0), 1);
+    }
+}
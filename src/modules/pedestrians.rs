@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::modules::geometry::Rect;
+use crate::modules::lights::GreenLight;
+use crate::modules::simulation::Simulation;
+use crate::modules::vehicle::Position;
+
+pub const PEDESTRIAN_SIZE: u32 = 8;
+
+// The curbs at the corners of the intersection, where crosswalks start and end
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomRight,
+    BottomLeft,
+}
+
+// What the pedestrian signal of a crosswalk shows
+#[derive(PartialEq, Debug, Clone)]
+pub enum WalkSignal {
+    Walk,
+    // whoever is on the crosswalk finishes, nobody else starts
+    FlashingDontWalk,
+    DontWalk,
+}
+
+// Someone crossing one leg of an intersection, from one corner to the other
+#[derive(Debug, Clone, Serialize)]
+pub struct Pedestrian {
+    pub id: u64,
+    pub junction: usize,
+    // the leg of the intersection the crosswalk is on
+    pub crosswalk: Position,
+    pub from: Corner,
+    pub x: i32,
+    pub y: i32,
+    pub spawn_tick: u64,
+    // when it stepped off the curb
+    pub start_tick: Option<u64>,
+    pub exit_tick: Option<u64>,
+}
+
+impl Pedestrian {
+    // Waiting at the curb of `from`, just off the crosswalk
+    pub fn new(sim: &Simulation, junction: usize, crosswalk: Position, from: Corner) -> Self {
+        let rect = sim.junctions[junction].crosswalk(&crosswalk);
+        let size = PEDESTRIAN_SIZE as i32;
+        let (x, y) = match heading(&crosswalk, &from) {
+            (1, _) => (
+                rect.x() - size,
+                rect.y() + (rect.height() as i32 - size) / 2,
+            ),
+            (-1, _) => (rect.right(), rect.y() + (rect.height() as i32 - size) / 2),
+            (_, 1) => (rect.x() + (rect.width() as i32 - size) / 2, rect.y() - size),
+            _ => (rect.x() + (rect.width() as i32 - size) / 2, rect.bottom()),
+        };
+
+        Self {
+            id: sim.next_pedestrian_id,
+            junction,
+            crosswalk,
+            from,
+            x,
+            y,
+            spawn_tick: sim.tick,
+            start_tick: None,
+            exit_tick: None,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, PEDESTRIAN_SIZE, PEDESTRIAN_SIZE)
+    }
+
+    pub fn heading(&self) -> (i32, i32) {
+        heading(&self.crosswalk, &self.from)
+    }
+
+    // Reached the curb on the other side
+    pub fn has_crossed(&self, crosswalk: &Rect) -> bool {
+        let size = PEDESTRIAN_SIZE as i32;
+        match self.heading() {
+            (1, _) => self.x >= crosswalk.right(),
+            (-1, _) => self.x + size <= crosswalk.x(),
+            (_, 1) => self.y >= crosswalk.bottom(),
+            _ => self.y + size <= crosswalk.y(),
+        }
+    }
+
+    // Ticks spent at the curb before crossing
+    pub fn wait(&self) -> Option<u64> {
+        self.start_tick.map(|start| start - self.spawn_tick)
+    }
+
+    pub fn crossing_time(&self) -> Option<u64> {
+        self.start_tick
+            .zip(self.exit_tick)
+            .map(|(start, exit)| exit - start)
+    }
+}
+
+// The two corners a crosswalk connects
+pub fn corners(crosswalk: &Position) -> [Corner; 2] {
+    match crosswalk {
+        Position::Top => [Corner::TopLeft, Corner::TopRight],
+        Position::Right => [Corner::TopRight, Corner::BottomRight],
+        Position::Bottom => [Corner::BottomLeft, Corner::BottomRight],
+        Position::Left => [Corner::TopLeft, Corner::BottomLeft],
+    }
+}
+
+// Unit step from `from` towards the opposite corner of the crosswalk
+fn heading(crosswalk: &Position, from: &Corner) -> (i32, i32) {
+    match (crosswalk, from) {
+        (Position::Top | Position::Bottom, Corner::TopLeft | Corner::BottomLeft) => (1, 0),
+        (Position::Top | Position::Bottom, _) => (-1, 0),
+        (_, Corner::TopLeft | Corner::TopRight) => (0, 1),
+        _ => (0, -1),
+    }
+}
+
+// The crosswalks that can walk alongside `light`: the ones across the legs on
+// either side of its approach, which only its turning vehicles cross
+pub fn parallel_crosswalks(light: &GreenLight) -> [Position; 2] {
+    match light {
+        GreenLight::TopLeft | GreenLight::BottomRight => [Position::Right, Position::Left],
+        GreenLight::TopRight | GreenLight::BottomLeft => [Position::Top, Position::Bottom],
+    }
+}
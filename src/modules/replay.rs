@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+use crate::modules::config::Config;
+use crate::modules::lights::{GreenLight, SignalState};
+use crate::modules::pedestrians::Corner;
+use crate::modules::simulation::Simulation;
+use crate::modules::vehicle::{Direction, Position, VehicleClass};
+
+// Everything that can't be derived from the seed alone: what the user spawned
+// and when the light changed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SimEvent {
+    Spawn {
+        tick: u64,
+        // recordings from before road networks only had junction 0
+        #[serde(default)]
+        junction: usize,
+        start: Position,
+        direction: Direction,
+        // planned turns at the junctions after the first one, see
+        // `Vehicle::turns`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        turns: Vec<Direction>,
+        // recordings from before vehicle classes only had cars
+        #[serde(default)]
+        class: VehicleClass,
+    },
+    Light {
+        tick: u64,
+        #[serde(default)]
+        junction: usize,
+        green_light: GreenLight,
+        // the phase, see `Phase`
+        #[serde(default)]
+        concurrent: bool,
+        #[serde(default)]
+        left_arrow: bool,
+        // recordings from before amber and all-red only know green
+        #[serde(default)]
+        signal: SignalState,
+    },
+    Pedestrian {
+        tick: u64,
+        #[serde(default)]
+        junction: usize,
+        crosswalk: Position,
+        from: Corner,
+    },
+}
+
+impl SimEvent {
+    pub fn tick(&self) -> u64 {
+        match self {
+            SimEvent::Spawn { tick, .. }
+            | SimEvent::Light { tick, .. }
+            | SimEvent::Pedestrian { tick, .. } => *tick,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    // the config it was recorded with, see `Config::fingerprint`. Recordings
+    // from before it play with any config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<u64>,
+    pub events: Vec<SimEvent>,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read replay `{path}`: {err}"))?;
+
+        serde_json::from_str(&content).map_err(|err| format!("invalid replay `{path}`: {err}"))
+    }
+
+    // The same seed and events play out differently with other settings
+    pub fn check_config(&self, config: &Config) -> Result<(), String> {
+        match self.config {
+            Some(fingerprint) if fingerprint != config.fingerprint() => {
+                Err("it was recorded with a different config".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).unwrap();
+
+        std::fs::write(path, content).map_err(|err| format!("cannot write replay `{path}`: {err}"))
+    }
+}
+
+// Feeds recorded events back into a simulation, in place of the keyboard
+pub struct ReplayPlayer {
+    events: Vec<SimEvent>,
+    next: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            events: replay.events,
+            next: 0,
+        }
+    }
+
+    // Applies the events of the current tick, call it right before `step`
+    pub fn feed(&mut self, sim: &mut Simulation) {
+        while let Some(event) = self.events.get(self.next) {
+            if event.tick() > sim.tick {
+                break;
+            }
+            sim.apply(event);
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+}
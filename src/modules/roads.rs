@@ -0,0 +1,487 @@
+use crate::modules::config::Config;
+use crate::modules::geometry::{Point, Rect};
+use crate::modules::junction::Junction;
+use crate::modules::lights::{GreenLight, approach_light};
+use crate::modules::vehicle::{Direction, Position};
+
+// The road layout as a graph. Lanes run one way from node to node, along the
+// center line of the lane. Approach lanes lead up to the box of a junction,
+// exit lanes lead away from it, and movements join the two through the box.
+// An exit lane ending where another lane starts carries on into the next
+// junction. Vehicles follow the path of their movement, so a different junction
+// shape only takes different nodes, lanes and movements.
+pub struct RoadGraph {
+    pub nodes: Vec<Point>,
+    pub lanes: Vec<Lane>,
+    pub movements: Vec<Movement>,
+    // the box of each junction, where its movements cross
+    pub boxes: Vec<Rect>,
+}
+
+pub struct Lane {
+    pub id: usize,
+    pub junction: usize,
+    // the leg of the junction it lies on
+    pub leg: Position,
+    pub from: usize,
+    pub to: usize,
+    pub kind: LaneKind,
+}
+
+pub enum LaneKind {
+    // held at `stop_line` by the signal head `light`
+    Approach { light: GreenLight, stop_line: Rect },
+    Exit,
+}
+
+pub struct Movement {
+    pub id: usize,
+    pub from_lane: usize,
+    pub to_lane: usize,
+    pub direction: Direction,
+    // corners between the end of `from_lane` and the start of `to_lane`
+    pub path: Vec<Point>,
+    // left turns only: where the path crosses the lane of oncoming traffic
+    pub conflict: Option<Rect>,
+}
+
+impl Lane {
+    pub fn light(&self) -> Option<&GreenLight> {
+        match &self.kind {
+            LaneKind::Approach { light, .. } => Some(light),
+            LaneKind::Exit => None,
+        }
+    }
+
+    pub fn stop_line(&self) -> Option<Rect> {
+        match &self.kind {
+            LaneKind::Approach { stop_line, .. } => Some(*stop_line),
+            LaneKind::Exit => None,
+        }
+    }
+}
+
+impl RoadGraph {
+    // Four-leg crossings with one lane each way, in the middle of every
+    // junction's area, and every movement but U-turns
+    pub fn grid(junctions: &[Junction], config: &Config) -> Self {
+        let mut graph = Self {
+            nodes: Vec::new(),
+            lanes: Vec::new(),
+            movements: Vec::new(),
+            boxes: Vec::new(),
+        };
+        let size = config.road.size as i32;
+        let half = size / 2;
+        // the crosswalk lies between the stop line and the box
+        let offset = config.pedestrians.crosswalk_width as i32 + 5;
+
+        for junction in junctions {
+            let (c, area) = (junction.center, junction.area);
+            let mut approaches = Vec::new();
+            let mut exits = Vec::new();
+
+            for leg in Position::ALL {
+                // the center lines of both lanes of the leg: into the box from
+                // the edge of the area, and back out
+                let (inbound, outbound) = match leg {
+                    Position::Top => (
+                        (
+                            Point::new(c.x - half, area.y()),
+                            Point::new(c.x - half, c.y - size),
+                        ),
+                        (
+                            Point::new(c.x + half, c.y - size),
+                            Point::new(c.x + half, area.y()),
+                        ),
+                    ),
+                    Position::Right => (
+                        (
+                            Point::new(area.right(), c.y - half),
+                            Point::new(c.x + size, c.y - half),
+                        ),
+                        (
+                            Point::new(c.x + size, c.y + half),
+                            Point::new(area.right(), c.y + half),
+                        ),
+                    ),
+                    Position::Bottom => (
+                        (
+                            Point::new(c.x + half, area.bottom()),
+                            Point::new(c.x + half, c.y + size),
+                        ),
+                        (
+                            Point::new(c.x - half, c.y + size),
+                            Point::new(c.x - half, area.bottom()),
+                        ),
+                    ),
+                    Position::Left => (
+                        (
+                            Point::new(area.x(), c.y + half),
+                            Point::new(c.x - size, c.y + half),
+                        ),
+                        (
+                            Point::new(c.x - size, c.y - half),
+                            Point::new(area.x(), c.y - half),
+                        ),
+                    ),
+                };
+
+                let kind = LaneKind::Approach {
+                    light: approach_light(&leg),
+                    stop_line: stop_line(inbound.1, &leg, size, offset),
+                };
+                approaches.push(graph.add_lane(junction.index, leg.clone(), inbound, kind));
+                exits.push(graph.add_lane(junction.index, leg, outbound, LaneKind::Exit));
+            }
+
+            for &from_lane in &approaches {
+                let leg = graph.lanes[from_lane].leg.clone();
+                for direction in [Direction::Left, Direction::Straight, Direction::Right] {
+                    let exit_leg = exit_leg(&leg, &direction);
+                    let to_lane = *exits
+                        .iter()
+                        .find(|&&lane| graph.lanes[lane].leg == exit_leg)
+                        .unwrap();
+                    let oncoming = *approaches
+                        .iter()
+                        .find(|&&lane| graph.lanes[lane].leg == leg.opposite())
+                        .unwrap();
+                    graph.add_movement(from_lane, to_lane, direction, oncoming, half);
+                }
+            }
+
+            // the box spans the inner ends of all lanes
+            let inner: Vec<Point> = approaches
+                .iter()
+                .map(|&lane| graph.end(lane))
+                .chain(exits.iter().map(|&lane| graph.start(lane)))
+                .collect();
+            let (left, top) = (
+                inner.iter().map(|point| point.x).min().unwrap(),
+                inner.iter().map(|point| point.y).min().unwrap(),
+            );
+            let (right, bottom) = (
+                inner.iter().map(|point| point.x).max().unwrap(),
+                inner.iter().map(|point| point.y).max().unwrap(),
+            );
+            graph.boxes.push(Rect::new(
+                left,
+                top,
+                (right - left) as u32,
+                (bottom - top) as u32,
+            ));
+        }
+
+        graph
+    }
+
+    // The node at `point`, shared by every lane that starts or ends there
+    fn node(&mut self, point: Point) -> usize {
+        match self.nodes.iter().position(|node| *node == point) {
+            Some(index) => index,
+            None => {
+                self.nodes.push(point);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn add_lane(
+        &mut self,
+        junction: usize,
+        leg: Position,
+        (start, end): (Point, Point),
+        kind: LaneKind,
+    ) -> usize {
+        let lane = Lane {
+            id: self.lanes.len(),
+            junction,
+            leg,
+            from: self.node(start),
+            to: self.node(end),
+            kind,
+        };
+        self.lanes.push(lane);
+        self.lanes.len() - 1
+    }
+
+    // Through the box from `from_lane` to `to_lane`, turning once at the
+    // crossing of both center lines
+    fn add_movement(
+        &mut self,
+        from_lane: usize,
+        to_lane: usize,
+        direction: Direction,
+        oncoming: usize,
+        half: i32,
+    ) {
+        let (end, start) = (self.end(from_lane), self.start(to_lane));
+        let vertical = matches!(self.lanes[from_lane].leg, Position::Top | Position::Bottom);
+        let path = match (&direction, vertical) {
+            (Direction::Straight, _) => Vec::new(),
+            (_, true) => vec![Point::new(end.x, start.y)],
+            (_, false) => vec![Point::new(start.x, end.y)],
+        };
+
+        // the exit lane crosses the oncoming approach lane there
+        let oncoming = self.end(oncoming);
+        let conflict = (direction == Direction::Left).then(|| {
+            let crossing = if vertical {
+                Point::new(oncoming.x, start.y)
+            } else {
+                Point::new(start.x, oncoming.y)
+            };
+            Rect::new(
+                crossing.x - half,
+                crossing.y - half,
+                (half * 2) as u32,
+                (half * 2) as u32,
+            )
+        });
+
+        self.movements.push(Movement {
+            id: self.movements.len(),
+            from_lane,
+            to_lane,
+            direction,
+            path,
+            conflict,
+        });
+    }
+
+    pub fn start(&self, lane: usize) -> Point {
+        self.nodes[self.lanes[lane].from]
+    }
+
+    pub fn end(&self, lane: usize) -> Point {
+        self.nodes[self.lanes[lane].to]
+    }
+
+    // For an exit lane carrying on into the next junction, that junction's
+    // approach lane and the pixels from the start of the exit to its stop line
+    pub fn link(&self, exit: usize) -> Option<(usize, f64)> {
+        let next = self.next_lane(exit)?;
+        let stop_line = self.lanes[next].stop_line()?;
+        let start = self.start(exit);
+        let (x, y) = (
+            stop_line.x() + stop_line.width() as i32 / 2,
+            stop_line.y() + stop_line.height() as i32 / 2,
+        );
+        let length = (x - start.x).abs() + (y - start.y).abs() - 10;
+        Some((next, length as f64))
+    }
+
+    // The lane vehicles coming from `leg` of `junction` arrive on
+    pub fn approach(&self, junction: usize, leg: &Position) -> &Lane {
+        self.lanes
+            .iter()
+            .find(|lane| {
+                lane.junction == junction
+                    && lane.leg == *leg
+                    && matches!(lane.kind, LaneKind::Approach { .. })
+            })
+            .unwrap()
+    }
+
+    pub fn movement(&self, lane: usize, direction: &Direction) -> &Movement {
+        self.movements
+            .iter()
+            .find(|movement| movement.from_lane == lane && movement.direction == *direction)
+            .unwrap()
+    }
+
+    // Every point to drive through from the end of the approach lane to the
+    // end of the exit lane
+    pub fn route(&self, movement: &Movement) -> Vec<Point> {
+        let mut route = vec![self.end(movement.from_lane)];
+        route.extend(&movement.path);
+        route.push(self.start(movement.to_lane));
+        route.push(self.end(movement.to_lane));
+        route
+    }
+
+    // Whether two movements through the same box cross or merge into the
+    // same exit. Movements out of the same lane follow each other instead.
+    pub fn crosses(&self, a: &Movement, b: &Movement) -> bool {
+        if a.from_lane == b.from_lane {
+            return false;
+        }
+        if a.to_lane == b.to_lane {
+            return true;
+        }
+
+        let (a, b) = (self.through_box(a), self.through_box(b));
+        a.windows(2)
+            .any(|a| b.windows(2).any(|b| segments_meet(a, b)))
+    }
+
+    // The center line of a movement from where it enters the box to where it
+    // leaves it
+    fn through_box(&self, movement: &Movement) -> Vec<Point> {
+        let mut path = vec![self.end(movement.from_lane)];
+        path.extend(&movement.path);
+        path.push(self.start(movement.to_lane));
+        path
+    }
+
+    // The lane that carries on where `lane` ends, if any
+    pub fn next_lane(&self, lane: usize) -> Option<usize> {
+        let end = self.lanes[lane].to;
+        self.lanes
+            .iter()
+            .find(|next| next.from == end)
+            .map(|next| next.id)
+    }
+
+    // Approach lanes no other lane leads into, on the edge of the network
+    pub fn entries(&self) -> Vec<&Lane> {
+        self.lanes
+            .iter()
+            .filter(|lane| {
+                matches!(lane.kind, LaneKind::Approach { .. })
+                    && !self.lanes.iter().any(|other| other.to == lane.from)
+            })
+            .collect()
+    }
+
+    pub fn is_entry(&self, junction: usize, leg: &Position) -> bool {
+        self.entries()
+            .iter()
+            .any(|lane| lane.junction == junction && lane.leg == *leg)
+    }
+
+    // Exit lanes that lead into no other lane, on the edge of the network
+    pub fn exits(&self) -> Vec<&Lane> {
+        self.lanes
+            .iter()
+            .filter(|lane| {
+                matches!(lane.kind, LaneKind::Exit)
+                    && !self.lanes.iter().any(|other| other.from == lane.to)
+            })
+            .collect()
+    }
+
+    // The turns to take at each junction on the way from the approach lane
+    // `from` to the exit lane `to`, through as few junctions as possible and
+    // going straight first where routes are as short. `None` when `to` can't
+    // be reached, the exit next to the entry for instance, as there are no
+    // U-turns.
+    pub fn plan_route(&self, from: usize, to: usize) -> Option<Vec<Direction>> {
+        let mut visited = vec![false; self.lanes.len()];
+        let mut queue = std::collections::VecDeque::from([(from, Vec::new())]);
+        visited[from] = true;
+
+        while let Some((lane, turns)) = queue.pop_front() {
+            for direction in [Direction::Straight, Direction::Left, Direction::Right] {
+                let exit = self.movement(lane, &direction).to_lane;
+                let mut turns = turns.clone();
+                turns.push(direction);
+                if exit == to {
+                    return Some(turns);
+                }
+                if let Some(next) = self.next_lane(exit)
+                    && !visited[next]
+                {
+                    visited[next] = true;
+                    queue.push_back((next, turns));
+                }
+            }
+        }
+
+        None
+    }
+
+    // Whether `rect` overlaps the box of `junction`
+    pub fn in_box(&self, junction: usize, rect: Rect) -> bool {
+        self.boxes[junction].has_intersection(rect)
+    }
+}
+
+// Whether two horizontal or vertical segments touch or cross, which their
+// bounding boxes tell
+fn segments_meet(a: &[Point], b: &[Point]) -> bool {
+    let span = |p: i32, q: i32| (p.min(q), p.max(q));
+    let (a_x, a_y) = (span(a[0].x, a[1].x), span(a[0].y, a[1].y));
+    let (b_x, b_y) = (span(b[0].x, b[1].x), span(b[0].y, b[1].y));
+    a_x.0 <= b_x.1 && b_x.0 <= a_x.1 && a_y.0 <= b_y.1 && b_y.0 <= a_y.1
+}
+
+// The exit a vehicle coming from `leg` takes to go in `direction`
+fn exit_leg(leg: &Position, direction: &Direction) -> Position {
+    match (direction, leg) {
+        (Direction::Straight, _) => leg.opposite(),
+        (Direction::Left, Position::Top) | (Direction::Right, Position::Bottom) => Position::Right,
+        (Direction::Left, Position::Right) | (Direction::Right, Position::Left) => Position::Bottom,
+        (Direction::Left, Position::Bottom) | (Direction::Right, Position::Top) => Position::Left,
+        (Direction::Left, Position::Left) | (Direction::Right, Position::Right) => Position::Top,
+    }
+}
+
+// A band across an approach lane ending at `end`, `offset` before it
+fn stop_line(end: Point, leg: &Position, size: i32, offset: i32) -> Rect {
+    let half = size / 2;
+    match leg {
+        Position::Top => Rect::new(end.x - half, end.y - offset - 10, size as u32, 20),
+        Position::Right => Rect::new(end.x + offset - 10, end.y - half, 20, size as u32),
+        Position::Bottom => Rect::new(end.x - half, end.y + offset - 10, size as u32, 20),
+        Position::Left => Rect::new(end.x - offset - 10, end.y - half, 20, size as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::simulation::Simulation;
+
+    fn corridor() -> Simulation {
+        let mut config = Config::default();
+        config.network.columns = 3;
+        Simulation::new(&config, 1)
+    }
+
+    fn exit(roads: &RoadGraph, junction: usize, leg: Position) -> usize {
+        roads
+            .lanes
+            .iter()
+            .find(|lane| {
+                matches!(lane.kind, LaneKind::Exit) && lane.junction == junction && lane.leg == leg
+            })
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn route_goes_straight_through_the_corridor() {
+        let sim = corridor();
+        let roads = &sim.roads;
+        let from = roads.approach(0, &Position::Left).id;
+        assert_eq!(
+            roads.plan_route(from, exit(roads, 2, Position::Right)),
+            Some(vec![Direction::Straight; 3])
+        );
+    }
+
+    #[test]
+    fn route_turns_where_it_leaves() {
+        let sim = corridor();
+        let roads = &sim.roads;
+        let from = roads.approach(0, &Position::Left).id;
+        assert_eq!(
+            roads.plan_route(from, exit(roads, 1, Position::Top)),
+            Some(vec![Direction::Straight, Direction::Left])
+        );
+        assert_eq!(
+            roads.plan_route(from, exit(roads, 0, Position::Bottom)),
+            Some(vec![Direction::Right])
+        );
+    }
+
+    #[test]
+    fn no_route_back_out_where_it_came_in() {
+        let sim = corridor();
+        let roads = &sim.roads;
+        let from = roads.approach(0, &Position::Left).id;
+        assert_eq!(roads.plan_route(from, exit(roads, 0, Position::Left)), None);
+    }
+}
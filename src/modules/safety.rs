@@ -0,0 +1,170 @@
+use serde::Serialize;
+
+use crate::modules::lights::{Aspect, approach_light};
+use crate::modules::simulation::Simulation;
+use crate::modules::vehicle::{Direction, Vehicle};
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    // the rectangles of two vehicles overlap
+    Overlap,
+    // a vehicle crossed its stop line while the light was red for it
+    RedLight,
+    // two vehicles whose paths cross are inside the box together
+    Conflict,
+}
+
+impl ViolationKind {
+    pub const ALL: [ViolationKind; 3] = [
+        ViolationKind::Overlap,
+        ViolationKind::RedLight,
+        ViolationKind::Conflict,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViolationKind::Overlap => "overlap",
+            ViolationKind::RedLight => "red light",
+            ViolationKind::Conflict => "conflict",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Violation {
+    pub tick: u64,
+    pub kind: ViolationKind,
+    pub junction: usize,
+    pub vehicle_id: u64,
+    // the other vehicle of a pair, the younger one
+    pub other_id: Option<u64>,
+}
+
+impl Violation {
+    fn new(tick: u64, kind: ViolationKind, vehicle: &Vehicle, other: Option<&Vehicle>) -> Self {
+        Self {
+            tick,
+            kind,
+            junction: vehicle.junction,
+            vehicle_id: vehicle.id,
+            other_id: other.map(|other| other.id),
+        }
+    }
+
+    fn same(&self, other: &Violation) -> bool {
+        self.kind == other.kind
+            && self.vehicle_id == other.vehicle_id
+            && self.other_id == other.other_id
+    }
+}
+
+// Records what `find_violations` turns up after every tick. A violation that
+// lasts, two vehicles overlapping for a while for instance, is recorded once
+// in the tick it starts.
+#[derive(Default)]
+pub struct SafetyMonitor {
+    pub violations: Vec<Violation>,
+    // the ones found in the previous tick
+    ongoing: Vec<Violation>,
+}
+
+impl SafetyMonitor {
+    pub fn record(&mut self, found: Vec<Violation>) {
+        for violation in &found {
+            if !self.ongoing.iter().any(|ongoing| ongoing.same(violation)) {
+                self.violations.push(violation.clone());
+            }
+        }
+        self.ongoing = found;
+    }
+}
+
+// Everything that is wrong right after the vehicles moved: overlapping
+// vehicles, vehicles that just crossed their stop line on red, and vehicles on
+// conflicting paths inside the same box
+pub fn find_violations(sim: &Simulation) -> Vec<Violation> {
+    let mut found = Vec::new();
+
+    for vehicle in &sim.vehicles {
+        let rect = vehicle.rect();
+        // every pair once, the older vehicle first
+        for other in sim.vehicles_near(rect) {
+            if other.id > vehicle.id && other.rect().has_intersection(rect) {
+                found.push(Violation::new(
+                    sim.tick,
+                    ViolationKind::Overlap,
+                    vehicle,
+                    Some(other),
+                ));
+            }
+        }
+
+        // detectors mark the crossing in the tick it happens. An amber may
+        // still be crossed, a left arrow only by left turns.
+        if vehicle.trip.crossing_tick == Some(sim.tick) {
+            let junction = &sim.junctions[vehicle.junction];
+            let passed_red = match junction.aspect(&approach_light(&vehicle.trip.origin)) {
+                Aspect::Red => true,
+                Aspect::LeftArrow => vehicle.direction != Direction::Left,
+                Aspect::Green | Aspect::Amber => false,
+            };
+            if passed_red {
+                found.push(Violation::new(
+                    sim.tick,
+                    ViolationKind::RedLight,
+                    vehicle,
+                    None,
+                ));
+            }
+        }
+    }
+
+    for area in &sim.roads.boxes {
+        let inside: Vec<&Vehicle> = sim
+            .vehicles_near(*area)
+            .filter(|vehicle| vehicle.rect().has_intersection(*area))
+            .collect();
+        for (index, vehicle) in inside.iter().enumerate() {
+            for other in &inside[index + 1..] {
+                if conflicting(sim, vehicle, other) {
+                    found.push(Violation::new(
+                        sim.tick,
+                        ViolationKind::Conflict,
+                        vehicle,
+                        Some(other),
+                    ));
+                }
+            }
+        }
+    }
+
+    found
+}
+
+// Whether two vehicles in the same box may not be there together. A
+// permissive left turn waits inside the box for a gap in oncoming traffic, so
+// against straight ahead and right turning oncoming vehicles it only counts
+// once both are on the crossing.
+fn conflicting(sim: &Simulation, vehicle: &Vehicle, other: &Vehicle) -> bool {
+    let roads = &sim.roads;
+    let (movement, other_movement) = (
+        &roads.movements[vehicle.movement],
+        &roads.movements[other.movement],
+    );
+    if !roads.crosses(movement, other_movement) {
+        return false;
+    }
+
+    for (left, oncoming) in [(vehicle, other), (other, vehicle)] {
+        if let Some(conflict) = roads.movements[left.movement].conflict
+            && oncoming.direction != Direction::Left
+            && oncoming.trip.origin == left.trip.origin.opposite()
+        {
+            return left.rect().has_intersection(conflict)
+                && oncoming.rect().has_intersection(conflict);
+        }
+    }
+
+    true
+}
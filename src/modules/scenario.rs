@@ -0,0 +1,182 @@
+use serde::Deserialize;
+
+use crate::modules::lights::GreenLight;
+use crate::modules::pedestrians::{Corner, corners};
+use crate::modules::simulation::Simulation;
+use crate::modules::vehicle::{Direction, Position, VehicleClass};
+
+// A reproducible traffic pattern, written in TOML (or JSON for `.json` files):
+//
+//   ticks = 3600
+//   initial_light = "TopLeft"
+//
+//   [[arrivals]]
+//   tick = 0
+//   from = "Top"
+//   turn = "Left"    # optional, picked with the seeded RNG when omitted
+//   junction = 0     # optional, in a network of several
+//
+//   [[pedestrians]]
+//   tick = 0
+//   crosswalk = "Top"
+//   from = "TopLeft" # optional, one of the crosswalk's two corners
+//   junction = 0     # optional
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub ticks: Option<u64>,
+    pub seed: Option<u64>,
+    pub initial_light: Option<GreenLight>,
+    #[serde(default)]
+    pub arrivals: Vec<Arrival>,
+    #[serde(default)]
+    pub pedestrians: Vec<PedestrianArrival>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Arrival {
+    pub tick: u64,
+    pub from: Position,
+    pub turn: Option<Direction>,
+    pub class: Option<VehicleClass>,
+    #[serde(default)]
+    pub junction: usize,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PedestrianArrival {
+    pub tick: u64,
+    pub crosswalk: Position,
+    pub from: Option<Corner>,
+    #[serde(default)]
+    pub junction: usize,
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read scenario `{path}`: {err}"))?;
+
+        let scenario: Result<Self, String> = if path.ends_with(".json") {
+            serde_json::from_str(&content).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&content).map_err(|err| err.to_string())
+        };
+
+        let scenario = scenario.map_err(|err| format!("invalid scenario `{path}`: {err}"))?;
+        for pedestrian in &scenario.pedestrians {
+            let corners = corners(&pedestrian.crosswalk);
+            if let Some(from) = pedestrian
+                .from
+                .as_ref()
+                .filter(|from| !corners.contains(from))
+            {
+                return Err(format!(
+                    "invalid scenario `{path}`: the {:?} crosswalk doesn't start at {from:?}",
+                    pedestrian.crosswalk
+                ));
+            }
+        }
+
+        Ok(scenario)
+    }
+
+    // Arrivals have to enter on the edge of the simulated network, and
+    // pedestrians cross at one of its junctions
+    pub fn check_network(&self, sim: &Simulation) -> Result<(), String> {
+        for arrival in &self.arrivals {
+            if !sim.roads.is_entry(arrival.junction, &arrival.from) {
+                return Err(format!(
+                    "the arrival at tick {} doesn't enter the network at junction {} from {:?}",
+                    arrival.tick, arrival.junction, arrival.from
+                ));
+            }
+        }
+        for pedestrian in &self.pedestrians {
+            if pedestrian.junction >= sim.junctions.len() {
+                return Err(format!(
+                    "the pedestrian at tick {} crosses at junction {}, which doesn't exist",
+                    pedestrian.tick, pedestrian.junction
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Spawns the arrivals of a scenario as they become due. An arrival that hits
+// its entry's cooldown waits for it, and so does everything queued behind it.
+pub struct ScenarioPlayer {
+    arrivals: Vec<Arrival>,
+    next: usize,
+    waiting: Vec<Arrival>,
+    pedestrians: Vec<PedestrianArrival>,
+    next_pedestrian: usize,
+}
+
+impl ScenarioPlayer {
+    pub fn new(scenario: &Scenario) -> Self {
+        let mut arrivals = scenario.arrivals.clone();
+        arrivals.sort_by_key(|arrival| arrival.tick);
+        let mut pedestrians = scenario.pedestrians.clone();
+        pedestrians.sort_by_key(|pedestrian| pedestrian.tick);
+
+        Self {
+            arrivals,
+            next: 0,
+            waiting: Vec::new(),
+            pedestrians,
+            next_pedestrian: 0,
+        }
+    }
+
+    pub fn feed(&mut self, sim: &mut Simulation) {
+        while let Some(arrival) = self.arrivals.get(self.next) {
+            if arrival.tick > sim.tick {
+                break;
+            }
+            self.waiting.push(arrival.clone());
+            self.next += 1;
+        }
+
+        while let Some(pedestrian) = self.pedestrians.get(self.next_pedestrian) {
+            if pedestrian.tick > sim.tick {
+                break;
+            }
+            sim.spawn_pedestrian(
+                pedestrian.junction,
+                pedestrian.crosswalk.clone(),
+                pedestrian.from.clone(),
+            );
+            self.next_pedestrian += 1;
+        }
+
+        let mut blocked: Vec<(usize, Position)> = Vec::new();
+        self.waiting.retain(|arrival| {
+            let entry = (arrival.junction, arrival.from.clone());
+            if blocked.contains(&entry) {
+                return true;
+            }
+
+            let spawned = sim.spawn_turning(
+                arrival.junction,
+                arrival.from.clone(),
+                arrival.turn.clone(),
+                arrival.class.clone(),
+            );
+            if !spawned {
+                blocked.push(entry);
+            }
+            !spawned
+        });
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.arrivals.len()
+            && self.waiting.is_empty()
+            && self.next_pedestrian >= self.pedestrians.len()
+    }
+}
@@ -0,0 +1,867 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::modules::config::{ClassMix, Config, TurnRatios};
+use crate::modules::controller::*;
+use crate::modules::detectors::QueueDetector;
+use crate::modules::geometry::Rect;
+use crate::modules::gridlock::GridlockDetector;
+use crate::modules::junction::*;
+use crate::modules::lights::*;
+use crate::modules::pedestrians::*;
+use crate::modules::replay::SimEvent;
+use crate::modules::roads::RoadGraph;
+use crate::modules::safety::{SafetyMonitor, find_violations};
+use crate::modules::spatial::SpatialIndex;
+use crate::modules::vehicle::*;
+
+pub struct Simulation {
+    pub config: Config,
+    pub vehicles: Vec<Vehicle>,
+    // the intersections of the network, row by row
+    pub junctions: Vec<Junction>,
+    pub roads: RoadGraph,
+    pub width: u32,
+    pub height: u32,
+    pub road_size: u32,
+    pub tick: u64,
+    pub next_vehicle_id: u64,
+    // vehicles that left a junction
+    pub trips: Vec<Trip>,
+    // and the ones that left the network
+    pub journeys: Vec<Journey>,
+    pub pedestrians: Vec<Pedestrian>,
+    pub next_pedestrian_id: u64,
+    // pedestrians that reached the other side
+    pub pedestrians_crossed: Vec<Pedestrian>,
+    // one per approach of every junction
+    pub detectors: Vec<QueueDetector>,
+    // spawns and light changes so far, see `Replay`
+    pub events: Vec<SimEvent>,
+    // lights are driven by replayed events instead of the controller
+    pub replaying: bool,
+    // ticks at which a light turned green with a vehicle still in the box
+    pub uncleared_greens: Vec<u64>,
+    // overlaps, red light runners and conflicts in the box so far
+    pub safety: SafetyMonitor,
+    // standstills of the whole network so far
+    pub gridlock: GridlockDetector,
+    // all randomness comes from the seed, so it reproduces a whole run
+    rng: StdRng,
+    // turns at the junctions after the first one. A replay spawns without
+    // drawing from `rng`, so these come from a stream of their own.
+    turn_rng: StdRng,
+    // kept up to date with `vehicles`, see `vehicles_near`
+    index: SpatialIndex,
+}
+
+impl Simulation {
+    pub fn new(config: &Config, seed: u64) -> Self {
+        let network = &config.network;
+        let mut junctions = Vec::new();
+        for row in 0..network.rows {
+            for column in 0..network.columns {
+                junctions.push(Junction::new(config, junctions.len(), column, row));
+            }
+        }
+        let detectors = junctions
+            .iter()
+            .flat_map(|junction| {
+                Position::ALL.map(|approach| {
+                    QueueDetector::new(
+                        junction.index,
+                        approach,
+                        config.simulation.ticks_per_minute(),
+                    )
+                })
+            })
+            .collect();
+
+        let window = &config.window;
+        let index = SpatialIndex::new(
+            window.width,
+            window.height,
+            config.road.size * 2,
+            junctions.len(),
+        );
+
+        Self {
+            config: config.clone(),
+            vehicles: Vec::new(),
+            roads: RoadGraph::grid(&junctions, config),
+            junctions,
+            width: config.window.width,
+            height: config.window.height,
+            road_size: config.road.size,
+            tick: 0,
+            next_vehicle_id: 0,
+            trips: Vec::new(),
+            journeys: Vec::new(),
+            pedestrians: Vec::new(),
+            next_pedestrian_id: 0,
+            pedestrians_crossed: Vec::new(),
+            detectors,
+            events: Vec::new(),
+            replaying: false,
+            uncleared_greens: Vec::new(),
+            safety: SafetyMonitor::default(),
+            gridlock: GridlockDetector::default(),
+            rng: StdRng::seed_from_u64(seed),
+            turn_rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+            index,
+        }
+    }
+
+    // Vehicles that may overlap `area`, in the order of `vehicles`
+    pub fn vehicles_near(&self, area: Rect) -> impl Iterator<Item = &Vehicle> {
+        self.index
+            .query(area)
+            .into_iter()
+            .map(|index| &self.vehicles[index])
+    }
+
+    // Vehicles in the charge of `junction`, in the order of `vehicles`
+    pub fn vehicles_at(&self, junction: usize) -> impl Iterator<Item = &Vehicle> {
+        self.index
+            .at_junction(junction)
+            .iter()
+            .map(|&index| &self.vehicles[index])
+    }
+
+    // Spawns a vehicle coming from `start`, at one of the junctions on that
+    // edge of the network, unless its entry is still cooling down
+    pub fn spawn(&mut self, start: Position) -> bool {
+        let entries: Vec<usize> = self
+            .roads
+            .entries()
+            .iter()
+            .filter(|lane| lane.leg == start)
+            .map(|lane| lane.junction)
+            .collect();
+        let junction = match entries.len() {
+            1 => entries[0],
+            len => entries[self.rng.gen_range(0..len)],
+        };
+
+        self.spawn_turning(junction, start, None, None)
+    }
+
+    // Same as `spawn` at a given junction, the direction and the class are
+    // only picked at random when not given. A long, slow vehicle can still be
+    // in the way once the cooldown is over, then nothing spawns either.
+    pub fn spawn_turning(
+        &mut self,
+        junction: usize,
+        start: Position,
+        direction: Option<Direction>,
+        class: Option<VehicleClass>,
+    ) -> bool {
+        if *self.junctions[junction].cool_downs.get_mut(&start) > 0 {
+            return false;
+        }
+
+        let direction =
+            direction.unwrap_or_else(|| random_direction(&mut self.rng, &self.config.demand.turns));
+        self.spawn_routed(junction, start, vec![direction], class)
+    }
+
+    // Same again with the turn to take at every junction on the way, or the
+    // first ones of them, see `RoadGraph::plan_route`
+    pub fn spawn_routed(
+        &mut self,
+        junction: usize,
+        start: Position,
+        turns: Vec<Direction>,
+        class: Option<VehicleClass>,
+    ) -> bool {
+        if *self.junctions[junction].cool_downs.get_mut(&start) > 0 {
+            return false;
+        }
+
+        // a random class is drawn once per arrival, one that doesn't fit yet
+        // keeps it. Drawn again, long vehicles would lose out to short ones.
+        let drawn = class.is_none();
+        let class = class.unwrap_or_else(|| {
+            let held = &mut self.junctions[junction].held_classes;
+            match held.iter().position(|(entry, _)| *entry == start) {
+                Some(index) => held.remove(index).1,
+                None => random_class(&mut self.rng, &self.config.vehicle.mix),
+            }
+        });
+
+        let lane = self.roads.approach(junction, &start).id;
+        let rect = Vehicle::new(self, lane, turns[0].clone(), class.clone()).rect();
+        if self
+            .vehicles_near(rect)
+            .any(|vehicle| vehicle.rect().has_intersection(rect))
+        {
+            if drawn {
+                self.junctions[junction].held_classes.push((start, class));
+            }
+            return false;
+        }
+
+        self.add_vehicle(junction, start, turns, class);
+        true
+    }
+
+    // Any entry on the edge of the network
+    pub fn spawn_random(&mut self) -> bool {
+        let (junction, start) = self.random_entry();
+        self.spawn_turning(junction, start, None, None)
+    }
+
+    // An emergency vehicle at any entry, whatever the mix says
+    pub fn spawn_emergency(&mut self) -> bool {
+        let (junction, start) = self.random_entry();
+        self.spawn_turning(junction, start, None, Some(VehicleClass::Emergency))
+    }
+
+    fn random_entry(&mut self) -> (usize, Position) {
+        let entries: Vec<(usize, Position)> = self
+            .roads
+            .entries()
+            .iter()
+            .map(|lane| (lane.junction, lane.leg.clone()))
+            .collect();
+        entries[self.rng.gen_range(0..entries.len())].clone()
+    }
+
+    // Pedestrians have no cooldown, they queue up at the curb. The corner is
+    // picked at random when not given.
+    pub fn spawn_pedestrian(&mut self, junction: usize, crosswalk: Position, from: Option<Corner>) {
+        let from = from.unwrap_or_else(|| {
+            let [first, second] = corners(&crosswalk);
+            if self.rng.gen_bool(0.5) {
+                first
+            } else {
+                second
+            }
+        });
+
+        self.add_pedestrian(junction, crosswalk, from);
+    }
+
+    pub fn spawn_random_pedestrian(&mut self) {
+        let index = self.rng.gen_range(0..4 * self.junctions.len());
+        let crosswalk = Position::ALL[index % 4].clone();
+        self.spawn_pedestrian(index / 4, crosswalk, None);
+    }
+
+    // Spawns a random pedestrian with the configured rate per minute
+    pub fn maybe_spawn_pedestrian(&mut self) {
+        let chance = self.config.pedestrians.rate / self.config.simulation.ticks_per_minute();
+        if self.rng.gen_bool(chance) {
+            self.spawn_random_pedestrian();
+        }
+    }
+
+    fn add_pedestrian(&mut self, junction: usize, crosswalk: Position, from: Corner) {
+        self.pedestrians.push(Pedestrian::new(
+            self,
+            junction,
+            crosswalk.clone(),
+            from.clone(),
+        ));
+        self.next_pedestrian_id += 1;
+        self.events.push(SimEvent::Pedestrian {
+            tick: self.tick,
+            junction,
+            crosswalk,
+            from,
+        });
+    }
+
+    fn add_vehicle(
+        &mut self,
+        junction: usize,
+        start: Position,
+        mut turns: Vec<Direction>,
+        class: VehicleClass,
+    ) {
+        let cool_down = self
+            .config
+            .simulation
+            .ticks(self.config.timing.spawn_cool_down);
+        *self.junctions[junction].cool_downs.get_mut(&start) = cool_down as u32;
+
+        let direction = turns.remove(0);
+        let lane = self.roads.approach(junction, &start).id;
+        let mut vehicle = Vehicle::new(self, lane, direction.clone(), class.clone());
+        vehicle.turns = turns.clone();
+        self.index.insert(self.vehicles.len(), &vehicle);
+        self.vehicles.push(vehicle);
+        self.next_vehicle_id += 1;
+        self.events.push(SimEvent::Spawn {
+            tick: self.tick,
+            junction,
+            start,
+            direction,
+            turns,
+            class,
+        });
+    }
+
+    pub fn apply(&mut self, event: &SimEvent) {
+        match event {
+            SimEvent::Spawn {
+                junction,
+                start,
+                direction,
+                turns,
+                class,
+                ..
+            } => {
+                let turns = [direction.clone()]
+                    .into_iter()
+                    .chain(turns.clone())
+                    .collect();
+                self.add_vehicle(*junction, start.clone(), turns, class.clone())
+            }
+            SimEvent::Light {
+                junction,
+                green_light,
+                concurrent,
+                left_arrow,
+                signal,
+                ..
+            } => {
+                let phase = Phase {
+                    light: green_light.clone(),
+                    concurrent: *concurrent,
+                    left_arrow: *left_arrow,
+                };
+                self.set_signal(*junction, phase, signal.clone());
+            }
+            SimEvent::Pedestrian {
+                junction,
+                crosswalk,
+                from,
+                ..
+            } => self.add_pedestrian(*junction, crosswalk.clone(), from.clone()),
+        }
+    }
+
+    // Seconds of simulated time so far
+    pub fn time(&self) -> f64 {
+        self.config.simulation.seconds(self.tick)
+    }
+
+    // Advances the simulation by one tick of `timestep` seconds
+    pub fn step(&mut self) {
+        self.make_way();
+        if !self.replaying {
+            for index in 0..self.junctions.len() {
+                self.update_light_timing(index);
+            }
+        }
+
+        // decrement cooldown for each direction
+        for junction in &mut self.junctions {
+            let cool_downs = &mut junction.cool_downs;
+            cool_downs.top = cool_downs.top.saturating_sub(1);
+            cool_downs.right = cool_downs.right.saturating_sub(1);
+            cool_downs.bottom = cool_downs.bottom.saturating_sub(1);
+            cool_downs.left = cool_downs.left.saturating_sub(1);
+        }
+
+        self.hand_over();
+        self.index.rebuild(&self.vehicles);
+
+        // every vehicle sees the others where they were at the start of the tick
+        let obstacles: Vec<Option<Obstacle>> = self
+            .vehicles
+            .iter()
+            .map(|vehicle| vehicle.obstacle(self))
+            .collect();
+
+        for (vehicle, obstacle) in self.vehicles.iter_mut().zip(obstacles) {
+            let config = &self.config.vehicle;
+            let dt = self.config.simulation.timestep;
+            let class = config.class(&vehicle.class);
+            let acceleration = vehicle.acceleration(config, obstacle.as_ref());
+            let mut speed = (vehicle.speed + acceleration * dt).clamp(0.0, class.max_speed);
+            // braking harder than it would, it never drives into what's ahead
+            if let Some(obstacle) = &obstacle {
+                speed = speed.min(obstacle.gap.max(0.0) / dt);
+            }
+            let pixels = speed * dt * self.config.simulation.pixels_per_metre;
+            vehicle.speed = speed;
+            vehicle.blocker = obstacle.map(|obstacle| obstacle.blocker);
+            vehicle.advance(pixels);
+            vehicle.trip.distance += pixels;
+
+            let trip = &mut vehicle.trip;
+            if speed < STOPPED_SPEED {
+                trip.stopped_ticks += 1;
+                if !vehicle.stopped {
+                    trip.stops += 1;
+                }
+                if matches!(vehicle.blocker, Some(Blocker::StopLine(_)))
+                    && trip.first_stop_tick.is_none()
+                {
+                    trip.first_stop_tick = Some(self.tick);
+                }
+            }
+            vehicle.stopped = speed < STOPPED_SPEED;
+
+            if vehicle.trip.decision_tick.is_none()
+                && vehicle.is_in_area2(&self.roads.boxes[vehicle.junction])
+            {
+                vehicle.trip.decision_tick = Some(self.tick);
+            }
+        }
+
+        self.index.rebuild(&self.vehicles);
+        self.move_pedestrians();
+
+        for detector in &mut self.detectors {
+            let junction = &self.junctions[detector.junction];
+            let lane = self.roads.approach(detector.junction, &detector.approach);
+            let stop_line = lane.stop_line().unwrap();
+            let aspect = junction.aspect(&detector.light);
+            let nearby = self.index.at_junction(detector.junction);
+            detector.update(self.tick, &stop_line, &aspect, &mut self.vehicles, nearby);
+        }
+
+        // after the detectors, which tell who crossed a stop line
+        let violations = find_violations(self);
+        self.safety.record(violations);
+
+        let limit = self
+            .config
+            .simulation
+            .ticks(self.config.simulation.gridlock_time);
+        self.gridlock
+            .update(self.tick, &self.vehicles, &self.roads.boxes, limit);
+
+        self.tick += 1;
+    }
+
+    // Emergency vehicles that haven't reached their stop line yet. The vehicles
+    // ahead of one in its lane yield to it. With preemption on, the first one
+    // within `preemption_distance` of the stop line at each junction takes
+    // over the signal, see `update_light_timing`. This runs during replays
+    // too, only the lights come from the recording then.
+    fn make_way(&mut self) {
+        let signal = &self.config.signal;
+        let reach = signal.preemption_distance * self.config.simulation.pixels_per_metre;
+        for junction in &mut self.junctions {
+            junction.preempted_by = None;
+        }
+
+        let mut yielding = vec![false; self.vehicles.len()];
+        for at in 0..self.vehicles.len() {
+            let emergency = &self.vehicles[at];
+            if emergency.class != VehicleClass::Emergency || emergency.trip.crossing_tick.is_some()
+            {
+                continue;
+            }
+
+            for &other in self.index.at_junction(emergency.junction) {
+                let vehicle = &self.vehicles[other];
+                if other != at
+                    && vehicle.lane == emergency.lane
+                    && vehicle.trip.crossing_tick.is_none()
+                    && emergency.gap_to(vehicle) >= 0.0
+                {
+                    yielding[other] = true;
+                }
+            }
+
+            let stop_line = self.roads.lanes[emergency.lane].stop_line().unwrap();
+            let junction = &mut self.junctions[emergency.junction];
+            if signal.preemption
+                && junction.preempted_by.is_none()
+                && emergency.distance_to(&stop_line) <= reach
+            {
+                junction.preempted_by = Some(emergency.id);
+                self.vehicles[at].trip.preempted = true;
+            }
+        }
+
+        for (vehicle, yielding) in self.vehicles.iter_mut().zip(yielding) {
+            vehicle.yielding = yielding;
+        }
+    }
+
+    // Vehicles that drove out of their junction's area go on to the next
+    // junction, or leave the road on the edge of the network
+    fn hand_over(&mut self) {
+        let tick = self.tick;
+        let mut departed = Vec::new();
+
+        for vehicle in &mut self.vehicles {
+            let junction = &self.junctions[vehicle.junction];
+            if !junction.has_left(vehicle) {
+                continue;
+            }
+
+            let mut trip = vehicle.trip.clone();
+            trip.exit_tick = Some(tick);
+            vehicle.journey.add(&trip);
+            self.trips.push(trip);
+
+            let exit = self.roads.movements[vehicle.movement].to_lane;
+            match self.roads.next_lane(exit) {
+                Some(next) => {
+                    let direction = if vehicle.turns.is_empty() {
+                        random_direction(&mut self.turn_rng, &self.config.demand.turns)
+                    } else {
+                        vehicle.turns.remove(0)
+                    };
+                    vehicle.enter(&self.roads, next, tick, direction);
+                }
+                None => {
+                    let mut journey = vehicle.journey.clone();
+                    journey.exit_junction = Some(junction.index);
+                    journey.destination = Some(self.roads.lanes[exit].leg.clone());
+                    journey.exit_tick = Some(tick);
+                    self.journeys.push(journey);
+                    departed.push(vehicle.id);
+                }
+            }
+        }
+
+        self.vehicles
+            .retain(|vehicle| !departed.contains(&vehicle.id));
+    }
+
+    // Pedestrians step off the curb while their crosswalk shows walk and no
+    // vehicle is on it, then keep going at their pace until the other side
+    fn move_pedestrians(&mut self) {
+        let starting: Vec<bool> = self
+            .pedestrians
+            .iter()
+            .map(|pedestrian| {
+                let junction = &self.junctions[pedestrian.junction];
+                let crosswalk = junction.crosswalk(&pedestrian.crosswalk);
+                let signal = junction.walk_signal(&pedestrian.crosswalk, self.tick, &self.config);
+                pedestrian.start_tick.is_none()
+                    && signal == WalkSignal::Walk
+                    && !self
+                        .vehicles_near(crosswalk)
+                        .any(|vehicle| vehicle.rect().has_intersection(crosswalk))
+            })
+            .collect();
+
+        let pace = self.config.pedestrians.pace(&self.config.simulation);
+        let moving = self.tick.is_multiple_of(pace);
+        for (pedestrian, start) in self.pedestrians.iter_mut().zip(starting) {
+            if start {
+                pedestrian.start_tick = Some(self.tick);
+            }
+            if pedestrian.start_tick.is_some() && moving {
+                let (dx, dy) = pedestrian.heading();
+                pedestrian.x += dx;
+                pedestrian.y += dy;
+            }
+        }
+
+        let (tick, junctions, crossed) =
+            (self.tick, &self.junctions, &mut self.pedestrians_crossed);
+        self.pedestrians.retain(|pedestrian| {
+            let crosswalk = junctions[pedestrian.junction].crosswalk(&pedestrian.crosswalk);
+            if !pedestrian.has_crossed(&crosswalk) {
+                return true;
+            }
+
+            let mut pedestrian = pedestrian.clone();
+            pedestrian.exit_tick = Some(tick);
+            crossed.push(pedestrian);
+            false
+        });
+    }
+
+    // Green until the controller picks the next light, then amber and all-red
+    // for the configured times, the all-red for longer while vehicles are
+    // still on their way through the box, up to `max_all_red`. With both at
+    // zero this is a plain switch. Preemption overrules the controller.
+    pub fn update_light_timing(&mut self, index: usize) {
+        let (pedestrians, simulation) = (&self.config.pedestrians, &self.config.simulation);
+        let walk = simulation.ticks(pedestrians.walk + pedestrians.flashing_dont_walk) as usize;
+        let amber = simulation.ticks(self.config.signal.amber) as usize;
+        let all_red = simulation.ticks(self.config.signal.all_red) as usize;
+        let max_all_red = simulation.ticks(self.config.signal.max_all_red) as usize;
+
+        // a walk, once shown, runs to the end of its flashing don't walk
+        let junction = &self.junctions[index];
+        let light_time = junction.light_time(self.tick);
+        let walk_over = junction.walking.is_empty() || light_time >= walk;
+
+        if let Some(id) = junction.preempted_by {
+            // the green goes to the emergency vehicle's approach, alone, as
+            // soon as the clearance allows, and stays there until it crossed.
+            // Minimum green and walk are cut short.
+            let vehicle = self
+                .vehicles_at(index)
+                .find(|vehicle| vehicle.id == id)
+                .unwrap();
+            let light = approach_light(&vehicle.trip.origin);
+            let served =
+                junction.signal == SignalState::Green && vehicle.may_pass(&junction.aspect(&light));
+            if !served {
+                let junction = &mut self.junctions[index];
+                junction.next_phase = Some(Phase::split(light));
+                if junction.signal == SignalState::Green {
+                    let phase = junction.phase.clone();
+                    self.set_signal(index, phase, SignalState::Amber);
+                }
+            }
+        } else if junction.signal == SignalState::Green && walk_over {
+            let observation = self.observe(index);
+            let junction = &mut self.junctions[index];
+            if let Some(next) = junction.controller.next_phase(&observation) {
+                junction.next_phase = Some(next);
+                let phase = junction.phase.clone();
+                self.set_signal(index, phase, SignalState::Amber);
+            }
+        }
+
+        let junction = &self.junctions[index];
+        let light_time = junction.light_time(self.tick);
+        if junction.signal == SignalState::Amber && light_time >= amber {
+            let phase = junction.phase.clone();
+            self.set_signal(index, phase, SignalState::AllRed);
+        }
+
+        let light_time = self.junctions[index].light_time(self.tick);
+        let cleared = all_red == 0 || light_time >= max_all_red || !self.box_occupied(index);
+        let junction = &mut self.junctions[index];
+        if junction.signal == SignalState::AllRed && light_time >= all_red && cleared {
+            let next = junction.next_phase.take().unwrap();
+            self.set_signal(index, next, SignalState::Green);
+        }
+    }
+
+    // A vehicle is in the box, or on its way through it: past the stop line
+    // and not out the far side yet
+    fn box_occupied(&self, index: usize) -> bool {
+        let area = self.roads.boxes[index];
+        let clearing = self
+            .vehicles_at(index)
+            .any(|vehicle| vehicle.trip.crossing_tick.is_some() && !vehicle.is_past(&area));
+        clearing
+            || self
+                .vehicles_near(area)
+                .any(|vehicle| vehicle.is_in_area2(&area))
+    }
+
+    fn observe(&self, index: usize) -> Observation {
+        let junction = &self.junctions[index];
+        let approaches = self
+            .detectors
+            .iter()
+            .filter(|detector| detector.junction == index)
+            .map(|detector| ApproachState {
+                light: detector.light.clone(),
+                queue: detector.queue_lengths.last().copied().unwrap_or(0),
+                left_queue: self
+                    .vehicles_at(index)
+                    .filter(|vehicle| {
+                        vehicle.trip.origin == detector.approach
+                            && vehicle.direction == Direction::Left
+                            && vehicle.stopped
+                            && vehicle.trip.crossing_tick.is_none()
+                    })
+                    .count() as u32,
+                since_crossing: detector.crossings.last().map(|tick| self.tick - tick),
+            })
+            .collect();
+
+        // under concurrent phasing, a left turn stopped at the lane of oncoming
+        // traffic, waiting for a gap, doesn't hold the green. It goes once
+        // oncoming traffic stops.
+        let area = self.roads.boxes[index];
+        let concurrent = self.config.signal.phasing == Phasing::Concurrent;
+        let pixels_per_metre = self.config.simulation.pixels_per_metre;
+        let waiting_for_gap = |vehicle: &Vehicle| {
+            let conflict = self.roads.movements[vehicle.movement].conflict;
+            vehicle.stopped
+                && conflict.is_some_and(|conflict| {
+                    (0.0..pixels_per_metre).contains(&vehicle.distance_to(&conflict))
+                })
+        };
+        let box_occupied = self
+            .vehicles_near(area)
+            .any(|vehicle| vehicle.is_in_area2(&area) && !(concurrent && waiting_for_gap(vehicle)));
+
+        Observation {
+            phase: junction.phase.clone(),
+            green_time: junction.light_time(self.tick),
+            box_occupied,
+            approaches,
+        }
+    }
+
+    // Turns `light` green at every junction, along with the opposite one
+    // under concurrent phasing
+    pub fn set_light(&mut self, light: GreenLight) {
+        let phase = Phase {
+            light,
+            concurrent: self.config.signal.phasing == Phasing::Concurrent,
+            left_arrow: false,
+        };
+        for index in 0..self.junctions.len() {
+            self.set_signal(index, phase.clone(), SignalState::Green);
+        }
+    }
+
+    pub fn set_signal(&mut self, index: usize, phase: Phase, signal: SignalState) {
+        if signal == SignalState::Green && self.box_occupied(index) {
+            self.uncleared_greens.push(self.tick);
+        }
+
+        let junction = &mut self.junctions[index];
+
+        // vehicles too close to stop when the amber comes on drive through it
+        if signal == SignalState::Amber {
+            let stopping_distance = self.config.vehicle.stopping_distance;
+            let pixels_per_metre = self.config.simulation.pixels_per_metre;
+            for &at in self.index.at_junction(index) {
+                let vehicle = &mut self.vehicles[at];
+                let light = approach_light(&vehicle.trip.origin);
+                let stop_line = self.roads.lanes[vehicle.lane].stop_line().unwrap();
+                vehicle.clearing = phase.serves(&light)
+                    && (!phase.left_arrow || vehicle.direction == Direction::Left)
+                    && vehicle.trip.crossing_tick.is_none()
+                    && !vehicle.stopped
+                    && vehicle.distance_to(&stop_line) / pixels_per_metre
+                        <= stopping_distance.max(vehicle.braking_distance(&self.config.vehicle));
+            }
+        }
+
+        // a green comes with a walk on the crosswalks beside it, if someone
+        // is waiting there. Left arrows turn across them.
+        junction.walking = match signal {
+            SignalState::Green if !phase.left_arrow => parallel_crosswalks(&phase.light)
+                .into_iter()
+                .filter(|leg| {
+                    self.pedestrians.iter().any(|pedestrian| {
+                        pedestrian.junction == index
+                            && pedestrian.crosswalk == *leg
+                            && pedestrian.start_tick.is_none()
+                    })
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        self.events.push(SimEvent::Light {
+            tick: self.tick,
+            junction: index,
+            green_light: phase.light.clone(),
+            concurrent: phase.concurrent,
+            left_arrow: phase.left_arrow,
+            signal: signal.clone(),
+        });
+        junction.phase = phase;
+        junction.signal = signal;
+
+        // Reset for next cycle
+        junction.light_changed_at = self.tick;
+    }
+}
+
+// A class picked with the weights of `mix`
+pub fn random_class(rng: &mut StdRng, mix: &ClassMix) -> VehicleClass {
+    let total: f64 = VehicleClass::ALL
+        .iter()
+        .map(|class| mix.weight(class))
+        .sum();
+    let mut pick = rng.gen_range(0.0..total);
+    for class in VehicleClass::ALL {
+        if pick < mix.weight(&class) {
+            return class;
+        }
+        pick -= mix.weight(&class);
+    }
+    VehicleClass::Car
+}
+
+// A direction picked with the weights of `turns`. Equal weights draw the way
+// it was done before there were any, so seeded runs stay the same.
+pub fn random_direction(rng: &mut StdRng, turns: &TurnRatios) -> Direction {
+    let directions = [Direction::Left, Direction::Straight, Direction::Right];
+    if turns.left == turns.straight && turns.straight == turns.right {
+        return directions[rng.gen_range(0..=2)].clone();
+    }
+    let total: f64 = directions
+        .iter()
+        .map(|direction| turns.weight(direction))
+        .sum();
+    let mut pick = rng.gen_range(0.0..total);
+    for direction in directions {
+        if pick < turns.weight(&direction) {
+            return direction;
+        }
+        pick -= turns.weight(&direction);
+    }
+    Direction::Straight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::traffic::Traffic;
+
+    fn run(config: &Config, seed: u64, ticks: u64) -> Simulation {
+        let mut sim = Simulation::new(config, seed);
+        let mut traffic = Traffic::Random;
+        while sim.tick < ticks {
+            traffic.feed(&mut sim);
+            sim.step();
+        }
+        sim
+    }
+
+    #[test]
+    fn random_traffic_runs_safely() {
+        let sim = run(&Config::default(), 1, 10_000);
+        assert!(!sim.trips.is_empty());
+        assert!(
+            sim.safety.violations.is_empty(),
+            "{:?}",
+            sim.safety.violations
+        );
+        assert!(
+            sim.gridlock.gridlocks.is_empty(),
+            "{:?}",
+            sim.gridlock.gridlocks
+        );
+    }
+
+    #[test]
+    fn concurrent_phasing_runs_safely() {
+        let mut config = Config::default();
+        config.signal.phasing = Phasing::Concurrent;
+        let sim = run(&config, 5, 10_000);
+        assert!(
+            sim.safety.violations.is_empty(),
+            "{:?}",
+            sim.safety.violations
+        );
+        assert!(
+            sim.gridlock.gridlocks.is_empty(),
+            "{:?}",
+            sim.gridlock.gridlocks
+        );
+    }
+
+    #[test]
+    fn network_runs_safely() {
+        let mut config = Config::default();
+        config.network.columns = 3;
+        let sim = run(&config, 2, 10_000);
+        assert!(
+            sim.safety.violations.is_empty(),
+            "{:?}",
+            sim.safety.violations
+        );
+        assert!(
+            sim.gridlock.gridlocks.is_empty(),
+            "{:?}",
+            sim.gridlock.gridlocks
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use crate::modules::geometry::Rect;
+use crate::modules::vehicle::Vehicle;
+
+// Where the vehicles are, so a vehicle only looks at the ones near it instead
+// of all of them. A grid of square cells over the window lists the vehicles
+// overlapping each cell, and every junction lists the vehicles it's in charge
+// of. Both hold positions in `Simulation::vehicles` in ascending order, so a
+// query finds vehicles in the same order as a scan of the whole list.
+pub struct SpatialIndex {
+    cell_size: u32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+    junctions: Vec<Vec<usize>>,
+}
+
+impl SpatialIndex {
+    pub fn new(width: u32, height: u32, cell_size: u32, junctions: usize) -> Self {
+        let columns = width.div_ceil(cell_size).max(1) as usize;
+        let rows = height.div_ceil(cell_size).max(1) as usize;
+
+        Self {
+            cell_size,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+            junctions: vec![Vec::new(); junctions],
+        }
+    }
+
+    // Indexes every vehicle afresh, after they moved or some left the list
+    pub fn rebuild(&mut self, vehicles: &[Vehicle]) {
+        self.cells.iter_mut().for_each(Vec::clear);
+        self.junctions.iter_mut().for_each(Vec::clear);
+        for (index, vehicle) in vehicles.iter().enumerate() {
+            self.insert(index, vehicle);
+        }
+    }
+
+    // Adds the vehicle at `index`, which has to be past every one indexed
+    pub fn insert(&mut self, index: usize, vehicle: &Vehicle) {
+        for cell in self.cells_of(vehicle.rect()) {
+            self.cells[cell].push(index);
+        }
+        self.junctions[vehicle.junction].push(index);
+    }
+
+    // Vehicles that may overlap `area`, whether they do is up to the caller
+    pub fn query(&self, area: Rect) -> Vec<usize> {
+        let mut found: Vec<usize> = self
+            .cells_of(area)
+            .flat_map(|cell| self.cells[cell].iter().copied())
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    pub fn at_junction(&self, junction: usize) -> &[usize] {
+        &self.junctions[junction]
+    }
+
+    // The cells `rect` covers. Whatever lies off the window counts for the
+    // cells on its border.
+    fn cells_of(&self, rect: Rect) -> impl Iterator<Item = usize> + use<> {
+        let column = |x: i32| (x.max(0) as usize / self.cell_size as usize).min(self.columns - 1);
+        let row = |y: i32| (y.max(0) as usize / self.cell_size as usize).min(self.rows - 1);
+        let (left, right) = (column(rect.x()), column(rect.right() - 1));
+        let (top, bottom) = (row(rect.y()), row(rect.bottom() - 1));
+        let columns = self.columns;
+        let empty = rect.width() == 0 || rect.height() == 0;
+
+        (top..=bottom)
+            .filter(move |_| !empty)
+            .flat_map(move |row| (left..=right).map(move |column| row * columns + column))
+    }
+}
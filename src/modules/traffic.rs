@@ -0,0 +1,42 @@
+use crate::modules::demand::DemandGenerator;
+use crate::modules::replay::ReplayPlayer;
+use crate::modules::scenario::ScenarioPlayer;
+use crate::modules::simulation::Simulation;
+
+// Where new vehicles come from
+pub enum Traffic {
+    // arrow keys and `R`, handled by the window itself
+    Keyboard,
+    // a random spawn attempt every tick, keeps every entry as busy as its
+    // cooldown allows, and pedestrians at the configured rate
+    Random,
+    // arrivals at the `[demand]` flows, the keyboard can add more
+    Demand(Box<DemandGenerator>),
+    Replay(ReplayPlayer),
+    Scenario(ScenarioPlayer),
+}
+
+impl Traffic {
+    // Spawns whatever is due this tick, call it right before `step`
+    pub fn feed(&mut self, sim: &mut Simulation) {
+        match self {
+            Traffic::Keyboard => {}
+            Traffic::Random => {
+                sim.spawn_random();
+                sim.maybe_spawn_pedestrian();
+            }
+            Traffic::Demand(generator) => generator.feed(sim),
+            Traffic::Replay(player) => player.feed(sim),
+            Traffic::Scenario(player) => player.feed(sim),
+        }
+    }
+
+    // Keyboard, random and demand traffic never run out
+    pub fn is_finished(&self) -> bool {
+        match self {
+            Traffic::Keyboard | Traffic::Random | Traffic::Demand(_) => false,
+            Traffic::Replay(player) => player.is_finished(),
+            Traffic::Scenario(player) => player.is_finished(),
+        }
+    }
+}
@@ -1,12 +1,20 @@
-use rand::Rng;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use serde::{Deserialize, Serialize};
 
-use crate::modules::view::View;
+use crate::modules::config::{Config, VehicleConfig};
+use crate::modules::geometry::{Point, Rect};
+use crate::modules::lights::{Aspect, GreenLight, approach_light};
+use crate::modules::roads::RoadGraph;
+use crate::modules::simulation::Simulation;
 
-#[derive(PartialEq, Debug, Clone)]
+// Below this speed, in m/s, a vehicle counts as standing still
+pub const STOPPED_SPEED: f64 = 0.2;
+// Metres looked ahead for obstacles, on top of what the speed takes
+const LOOKAHEAD: f64 = 10.0;
+// Metres a vehicle halts short of a stop line or an area it waits for. Without
+// it, a vehicle standing right at the line would see no reason to stay put.
+const STANDSTILL_GAP: f64 = 0.5;
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum Position {
     Top,
     Right,
@@ -14,23 +22,257 @@ pub enum Position {
     Left,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl Position {
+    pub const ALL: [Position; 4] = [
+        Position::Top,
+        Position::Right,
+        Position::Bottom,
+        Position::Left,
+    ];
+
+    pub fn opposite(&self) -> Position {
+        match self {
+            Position::Top => Position::Bottom,
+            Position::Right => Position::Left,
+            Position::Bottom => Position::Top,
+            Position::Left => Position::Right,
+        }
+    }
+
+    // One pixel of travel for a vehicle coming from this side
+    pub fn step(&self) -> (i32, i32) {
+        match self {
+            Position::Top => (0, 1),
+            Position::Right => (-1, 0),
+            Position::Bottom => (0, -1),
+            Position::Left => (1, 0),
+        }
+    }
+
+    // The side a vehicle driving from `from` straight to `to` comes from
+    pub fn toward(from: Point, to: Point) -> Position {
+        if to.y > from.y {
+            Position::Top
+        } else if to.y < from.y {
+            Position::Bottom
+        } else if to.x > from.x {
+            Position::Left
+        } else {
+            Position::Right
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum Direction {
     Straight,
     Right,
     Left,
 }
 
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VehicleClass {
+    #[default]
+    Car,
+    Truck,
+    Bus,
+    Motorcycle,
+    Bicycle,
+    // preempts the signals on its way, see `Simulation::make_way`
+    Emergency,
+}
+
+impl VehicleClass {
+    pub const ALL: [VehicleClass; 6] = [
+        VehicleClass::Car,
+        VehicleClass::Truck,
+        VehicleClass::Bus,
+        VehicleClass::Motorcycle,
+        VehicleClass::Bicycle,
+        VehicleClass::Emergency,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VehicleClass::Car => "car",
+            VehicleClass::Truck => "truck",
+            VehicleClass::Bus => "bus",
+            VehicleClass::Motorcycle => "motorcycle",
+            VehicleClass::Bicycle => "bicycle",
+            VehicleClass::Emergency => "emergency",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Vehicle {
-    pub x: i32,
-    pub y: i32,
+    pub id: u64,
+    // index of the junction it's driving through
+    pub junction: usize,
+    // top left corner
+    pub x: f64,
+    pub y: f64,
     pub width: u32,
     pub height: u32,
-    pub color: Color,
+    // size, top speed and acceleration come from its class, see `ClassConfig`
+    pub class: VehicleClass,
+    // in m/s
+    pub speed: f64,
+    // the side it's coming from, changes when turning
     pub start: Position,
-    pub decision_made: bool,
     pub direction: Direction,
+    // turns planned for the junctions after this one, see `RoadGraph::plan_route`.
+    // Past the end of them it picks at random.
+    pub turns: Vec<Direction>,
+    // the approach lane of the current junction and the movement it takes
+    // from there
+    pub lane: usize,
+    pub movement: usize,
+    // corners and lane ends ahead, see `RoadGraph::route`
+    pub path: Vec<Point>,
+    pub waypoint: usize,
+    // slower than `STOPPED_SPEED`
+    pub stopped: bool,
+    // caught by an amber too close to stop, keeps going until it turns red
+    pub clearing: bool,
+    // what it kept its distance to in the last tick
+    pub blocker: Option<Blocker>,
+    // an emergency vehicle is coming up behind it in its lane
+    pub yielding: bool,
+    pub trip: Trip,
+    pub journey: Journey,
+}
+
+// What happened to a vehicle between the moment it entered a junction's area
+// and the moment it left it. Ticks are simulation ticks.
+#[derive(Debug, Clone, Serialize)]
+pub struct Trip {
+    pub vehicle_id: u64,
+    pub junction: usize,
+    // `Vehicle::start` changes when turning, this doesn't
+    pub origin: Position,
+    pub direction: Direction,
+    pub class: VehicleClass,
+    // top speed of its class in pixels per tick, for the delay
+    pub free_speed: f64,
+    pub spawn_tick: u64,
+    // first time it waited at a red stop line
+    pub first_stop_tick: Option<u64>,
+    pub stopped_ticks: u64,
+    // how many times it came to a halt, for any reason
+    pub stops: u32,
+    // when its front reached the stop line of its approach
+    pub crossing_tick: Option<u64>,
+    pub decision_tick: Option<u64>,
+    pub exit_tick: Option<u64>,
+    // pixels travelled
+    pub distance: f64,
+    // emergency vehicles only: the signal was preempted for it
+    pub preempted: bool,
+}
+
+impl Trip {
+    pub fn new(
+        vehicle_id: u64,
+        junction: usize,
+        origin: Position,
+        direction: Direction,
+        class: VehicleClass,
+        free_speed: f64,
+        tick: u64,
+    ) -> Self {
+        Self {
+            vehicle_id,
+            junction,
+            origin,
+            direction,
+            class,
+            free_speed,
+            spawn_tick: tick,
+            first_stop_tick: None,
+            crossing_tick: None,
+            stopped_ticks: 0,
+            stops: 0,
+            decision_tick: None,
+            exit_tick: None,
+            distance: 0.0,
+            preempted: false,
+        }
+    }
+
+    pub fn travel_time(&self) -> Option<u64> {
+        self.exit_tick.map(|exit| exit - self.spawn_tick)
+    }
+
+    // Time lost compared to driving the same distance without ever stopping,
+    // at the top speed of its class
+    pub fn delay(&self) -> Option<u64> {
+        self.travel_time().map(|travel_time| {
+            travel_time.saturating_sub(free_time(self.distance, self.free_speed))
+        })
+    }
+}
+
+// A vehicle's way through the whole network, one trip per junction on it
+#[derive(Debug, Clone, Serialize)]
+pub struct Journey {
+    pub vehicle_id: u64,
+    pub origin: Position,
+    pub class: VehicleClass,
+    pub free_speed: f64,
+    pub entry_junction: usize,
+    pub exit_junction: Option<usize>,
+    // the leg of `exit_junction` it left by
+    pub destination: Option<Position>,
+    pub spawn_tick: u64,
+    pub exit_tick: Option<u64>,
+    // junctions driven through
+    pub junctions: u32,
+    pub stops: u32,
+    pub distance: f64,
+}
+
+impl Journey {
+    // Adds up a finished trip through one of the junctions
+    pub fn add(&mut self, trip: &Trip) {
+        self.junctions += 1;
+        self.stops += trip.stops;
+        self.distance += trip.distance;
+    }
+
+    pub fn travel_time(&self) -> Option<u64> {
+        self.exit_tick.map(|exit| exit - self.spawn_tick)
+    }
+
+    pub fn delay(&self) -> Option<u64> {
+        self.travel_time().map(|travel_time| {
+            travel_time.saturating_sub(free_time(self.distance, self.free_speed))
+        })
+    }
+}
+
+// Ticks it takes to drive `distance` pixels at `speed`
+fn free_time(distance: f64, speed: f64) -> u64 {
+    (distance / speed).round() as u64
+}
+
+// What a vehicle is holding back for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Blocker {
+    StopLine(GreenLight),
+    Vehicle(u64),
+    Pedestrian(u64),
+}
+
+// Something ahead a vehicle may not drive into, `gap` metres from its front
+// and moving at `speed` m/s. It comes to a halt `min_gap` before it.
+#[derive(Debug, Clone)]
+pub struct Obstacle {
+    pub blocker: Blocker,
+    pub gap: f64,
+    pub speed: f64,
+    pub min_gap: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -41,135 +283,525 @@ pub struct CoolDown {
     pub left: u32,
 }
 
-impl Vehicle {
-    pub fn new(view: &View, start: Position) -> Self {
-        let width: i32 = 25;
-        let height: i32 = 25;
-        let (x, y) = match start {
-            Position::Top => {
-                let x = view.center.x - view.road_size as i32 / 2 - width / 2;
-                let y = 0;
-                (x, y)
-            }
-            Position::Right => {
-                let x = view.width as i32 - height;
-                let y = view.height as i32 / 2 - view.road_size as i32 / 2 - width / 2;
-                (x, y)
-            }
-            Position::Bottom => {
-                let x = view.center.x + view.road_size as i32 / 2 - width / 2;
-                let y = view.height as i32 - height;
-                (x, y)
-            }
-            Position::Left => {
-                let x = 0;
-                let y = view.height as i32 / 2 + view.road_size as i32 / 2 - width / 2;
-                (x, y)
-            }
-        };
+impl CoolDown {
+    pub fn get_mut(&mut self, position: &Position) -> &mut u32 {
+        match position {
+            Position::Top => &mut self.top,
+            Position::Right => &mut self.right,
+            Position::Bottom => &mut self.bottom,
+            Position::Left => &mut self.left,
+        }
+    }
+}
 
-        let direction = match rand::thread_rng().gen_range(0..=2) {
-            0 => Direction::Left,
-            1 => Direction::Straight,
-            _ => Direction::Right,
+impl Vehicle {
+    // Entering the network on `lane`, with its rear at the start of it. It
+    // starts out at top speed.
+    pub fn new(sim: &Simulation, lane: usize, direction: Direction, class: VehicleClass) -> Self {
+        let roads = &sim.roads;
+        let (junction, start) = (roads.lanes[lane].junction, roads.lanes[lane].leg.clone());
+        let settings = sim.config.vehicle.class(&class);
+        let (length, breadth) = (settings.length, settings.width);
+        let (width, height) = match start {
+            Position::Top | Position::Bottom => (breadth, length),
+            Position::Right | Position::Left => (length, breadth),
         };
 
-        let color = match direction {
-            Direction::Straight => Color::RGB(255, 165, 0),
-            Direction::Right => Color::CYAN,
-            Direction::Left => Color::MAGENTA,
-        };
+        let (dx, dy) = start.step();
+        let entry = roads.start(lane);
+        let half_length = length as f64 / 2.0;
+        let x = entry.x as f64 + dx as f64 * half_length - width as f64 / 2.0;
+        let y = entry.y as f64 + dy as f64 * half_length - height as f64 / 2.0;
+        let movement = roads.movement(lane, &direction);
+        let simulation = &sim.config.simulation;
+        let free_speed = settings.max_speed * simulation.pixels_per_metre * simulation.timestep;
 
+        let id = sim.next_vehicle_id;
         Self {
+            id,
+            junction,
             x,
             y,
-            width: width as u32,
-            height: height as u32,
-            decision_made: false,
-            color,
+            width,
+            height,
+            speed: settings.max_speed,
+            lane,
+            movement: movement.id,
+            path: roads.route(movement),
+            waypoint: 0,
+            turns: Vec::new(),
+            stopped: false,
+            clearing: false,
+            blocker: None,
+            yielding: false,
+            trip: Trip::new(
+                id,
+                junction,
+                start.clone(),
+                direction.clone(),
+                class.clone(),
+                free_speed,
+                sim.tick,
+            ),
+            journey: Journey {
+                vehicle_id: id,
+                origin: start.clone(),
+                class: class.clone(),
+                free_speed,
+                entry_junction: junction,
+                exit_junction: None,
+                destination: None,
+                spawn_tick: sim.tick,
+                exit_tick: None,
+                junctions: 0,
+                stops: 0,
+                distance: 0.0,
+            },
             start,
             direction,
+            class,
         }
     }
 
-    pub fn draw(&self, canvas: &mut Canvas<Window>) {
-        canvas.set_draw_color(self.color);
-        canvas
-            .fill_rect(Rect::new(self.x, self.y, self.width, self.height))
-            .unwrap();
+    // Drives on into the next junction of the network on `lane`, where it
+    // picks a new direction and starts a new trip
+    pub fn enter(&mut self, roads: &RoadGraph, lane: usize, tick: u64, direction: Direction) {
+        let movement = roads.movement(lane, &direction);
+        self.junction = roads.lanes[lane].junction;
+        self.lane = lane;
+        self.movement = movement.id;
+        self.path = roads.route(movement);
+        self.waypoint = 0;
+        self.clearing = false;
+        self.trip = Trip::new(
+            self.id,
+            self.junction,
+            self.start.clone(),
+            direction.clone(),
+            self.class.clone(),
+            self.trip.free_speed,
+            tick,
+        );
+        self.direction = direction;
     }
 
-    pub fn can_move(&self, view: &View) -> bool {
+    // The pixels it covers
+    pub fn rect(&self) -> Rect {
+        Rect::new(
+            self.x.round() as i32,
+            self.y.round() as i32,
+            self.width,
+            self.height,
+        )
+    }
 
-        let next_rect = match self.start {
-            Position::Top => Rect::new(self.x, self.y + 1, self.width, self.height),
-            Position::Right => Rect::new(self.x - 1, self.y, self.width, self.height),
-            Position::Bottom => Rect::new(self.x, self.y - 1, self.width, self.height),
-            Position::Left => Rect::new(self.x + 1, self.y, self.width, self.height),
-        };
+    pub fn center(&self) -> (f64, f64) {
+        (
+            self.x + self.width as f64 / 2.0,
+            self.y + self.height as f64 / 2.0,
+        )
+    }
 
-        // Check traffic light stop lines
-        for (light_type, stop_line_rect) in &view.stop_lines {
-            if *light_type == view.green_light {
-                continue;
+    fn set_center(&mut self, (x, y): (f64, f64)) {
+        self.x = x - self.width as f64 / 2.0;
+        self.y = y - self.height as f64 / 2.0;
+    }
+
+    // Moves `distance` pixels ahead, turning when the center reaches a corner
+    // of the path. Past the end of the path it keeps going straight.
+    pub fn advance(&mut self, mut distance: f64) {
+        while distance > 0.0 {
+            let (x, y) = self.center();
+            let (dx, dy) = self.start.step();
+            let Some(&waypoint) = self.path.get(self.waypoint) else {
+                self.set_center((x + dx as f64 * distance, y + dy as f64 * distance));
+                return;
+            };
+
+            let left = (waypoint.x as f64 - x).abs() + (waypoint.y as f64 - y).abs();
+            if left > distance {
+                self.set_center((x + dx as f64 * distance, y + dy as f64 * distance));
+                return;
             }
 
-            if stop_line_rect.has_intersection(next_rect) {
-                return false;
+            self.set_center((waypoint.x as f64, waypoint.y as f64));
+            distance -= left;
+            self.waypoint += 1;
+            if let Some(&next) = self.path.get(self.waypoint) {
+                let side = Position::toward(waypoint, next);
+                if side != self.start {
+                    self.start = side;
+                    self.rotate();
+                }
             }
         }
+    }
 
-        for other in &view.vehicles {
-            if self.x == other.x && self.y == other.y {
-                continue;
+    // Intelligent Driver Model: speeds up towards the top speed of its class
+    // and brakes for `obstacle` so as to come to a halt `min_gap` before it,
+    // keeping `headway` seconds of distance at speed. Distances in metres.
+    pub fn acceleration(&self, config: &VehicleConfig, obstacle: Option<&Obstacle>) -> f64 {
+        let class = config.class(&self.class);
+        let free = 1.0 - (self.speed / class.max_speed).powi(4);
+        // yielding, it closes up on the vehicle ahead to clear the lane sooner
+        let headway = if self.yielding { 0.0 } else { config.headway };
+        let interaction = match obstacle {
+            None => 0.0,
+            // right up against it, it has to stop at once
+            Some(obstacle) if obstacle.gap <= 0.0 => return f64::NEG_INFINITY,
+            Some(obstacle) => {
+                let approach = self.speed * (self.speed - obstacle.speed)
+                    / (2.0 * (class.acceleration * class.deceleration).sqrt());
+                let desired = obstacle.min_gap + self.speed * headway + approach;
+                (desired.max(0.0) / obstacle.gap).powi(2)
             }
+        };
+
+        class.acceleration * (free - interaction)
+    }
+
+    // How far ahead obstacles matter, in pixels: the distance it keeps at its
+    // speed and what it needs to brake from there
+    fn lookahead(&self, config: &Config) -> u32 {
+        let vehicle = &config.vehicle;
+        let braking = self.speed * self.speed / vehicle.class(&self.class).deceleration;
+        let metres = LOOKAHEAD + self.speed * vehicle.headway + braking;
+        (metres * config.simulation.pixels_per_metre).ceil() as u32
+    }
 
-            let other_rect = Rect::new(other.x, other.y, other.width, other.height);
+    // The strip in front of the vehicle, as wide as itself
+    fn corridor(&self, length: u32) -> Rect {
+        let rect = self.rect();
+        let (width, height) = (rect.width(), rect.height());
+        match self.start {
+            Position::Top => Rect::new(rect.x(), rect.bottom(), width, length),
+            Position::Right => Rect::new(rect.x() - length as i32, rect.y(), length, height),
+            Position::Bottom => Rect::new(rect.x(), rect.y() - length as i32, width, length),
+            Position::Left => Rect::new(rect.right(), rect.y(), length, height),
+        }
+    }
 
-            if next_rect.has_intersection(other_rect) {
-                return false;
+    // Pixels from the center to the next corner of the path, and what the
+    // vehicle covers once it turned there
+    fn next_turn(&self) -> Option<(f64, Rect)> {
+        let (mut x, mut y) = self.center();
+        let mut distance = 0.0;
+        for (index, &waypoint) in self.path.iter().enumerate().skip(self.waypoint) {
+            distance += (waypoint.x as f64 - x).abs() + (waypoint.y as f64 - y).abs();
+            (x, y) = (waypoint.x as f64, waypoint.y as f64);
+            let next = self.path.get(index + 1)?;
+            if Position::toward(waypoint, *next) != self.start {
+                let turned = Rect::new(
+                    (x - self.height as f64 / 2.0).round() as i32,
+                    (y - self.width as f64 / 2.0).round() as i32,
+                    self.height,
+                    self.width,
+                );
+                return Some((distance, turned));
             }
+        }
+        None
+    }
 
-            let safety_distance = 30; // Adjust this value to control spacing
+    // What the vehicle has to keep its distance to, if anything: the one
+    // among the things ahead of it that makes it brake the hardest
+    pub fn obstacle(&self, sim: &Simulation) -> Option<Obstacle> {
+        let config = &sim.config.vehicle;
+        let pixels_per_metre = sim.config.simulation.pixels_per_metre;
+        let rect = self.rect();
+        let corridor = self.corridor(self.lookahead(&sim.config));
+        let junction = &sim.junctions[self.junction];
+        let mut obstacles = Vec::new();
+        // lines and areas it drives up to, `distance` pixels ahead
+        let mut hold_at = |blocker: Blocker, distance: f64| {
+            obstacles.push(Obstacle {
+                blocker,
+                gap: distance / pixels_per_metre,
+                speed: 0.0,
+                min_gap: STANDSTILL_GAP,
+            })
+        };
 
-            let too_close = match self.start {
-                Position::Top => {
-                    other.x == self.x && other.y > self.y && (other.y - self.y) < safety_distance
-                }
-                Position::Right => {
-                    other.y == self.y && other.x < self.x && (self.x - other.x) < safety_distance
-                }
-                Position::Bottom => {
-                    other.x == self.x && other.y < self.y && (self.y - other.y) < safety_distance
-                }
-                Position::Left => {
-                    other.y == self.y && other.x > self.x && (other.x - self.x) < safety_distance
-                }
+        // The stop line of the approach, until the detector saw it cross. A
+        // vehicle a hair past the line that didn't count as crossing yet is
+        // still held, like the detectors and the safety monitor have it.
+        let lane = &sim.roads.lanes[self.lane];
+        if let (Some(light), Some(stop_line)) = (lane.light(), lane.stop_line())
+            && self.trip.crossing_tick.is_none()
+            && !self.may_pass(&junction.aspect(light))
+        {
+            hold_at(
+                Blocker::StopLine(light.clone()),
+                self.distance_to(&stop_line),
+            );
+        }
+
+        // Don't block the box: wait outside while a vehicle from the same
+        // approach is stuck inside, a left turn waiting for a gap for instance.
+        // Left turns from opposite approaches cross each other's path, so they
+        // take turns, the one that came first going first when both are on
+        // their way in. So do a left turn and the opposite right turn, which
+        // merge into the same exit: one swinging round there would have no
+        // room left while the other waits behind it.
+        let area = sim.roads.boxes[self.junction];
+        let in_box = |rect: Rect| sim.roads.in_box(self.junction, rect);
+        let exit = sim.roads.movements[self.movement].to_lane;
+        if in_box(corridor) && !in_box(rect) {
+            let stuck = sim.vehicles_at(self.junction).find(|other| {
+                let same_lane = other.trip.origin == self.trip.origin && other.stopped;
+                let opposite = other.trip.origin == self.trip.origin.opposite();
+                let crossing = opposite
+                    && (self.direction == Direction::Left && other.direction == Direction::Left
+                        || sim.roads.movements[other.movement].to_lane == exit);
+                let entering = other.id < self.id
+                    && other.trip.crossing_tick.is_some()
+                    && !other.is_past(&area);
+                other.id != self.id
+                    && ((same_lane || crossing) && in_box(other.rect()) || crossing && entering)
+            });
+            if let Some(other) = stuck {
+                hold_at(Blocker::Vehicle(other.id), self.distance_to(&area));
+            }
+        }
+
+        // Nor does it cross the stop line until the road from its exit to the
+        // next junction has room for it, what the vehicles on that road and
+        // those on their way into it take up, each its length and the safety
+        // distance. Past the line it's committed, the all-red waits for it.
+        if let Some(stop_line) = lane.stop_line()
+            && self.trip.crossing_tick.is_none()
+            && let Some((next, length)) = sim.roads.link(exit)
+        {
+            let room = |vehicle: &Vehicle| {
+                config.class(&vehicle.class).length as f64
+                    + config.safety_distance * pixels_per_metre
             };
+            let on_link = sim
+                .vehicles_at(sim.roads.lanes[next].junction)
+                .filter(|other| other.lane == next && other.trip.crossing_tick.is_none());
+            let entering = sim.vehicles_at(self.junction).filter(|other| {
+                other.id != self.id
+                    && other.trip.crossing_tick.is_some()
+                    && sim.roads.movements[other.movement].to_lane == exit
+            });
+            let ahead: Vec<&Vehicle> = on_link.chain(entering).collect();
+            let taken: f64 = ahead.iter().map(|other| room(other)).sum();
+            // the one at the back of the queue it would join
+            let start = sim.roads.start(exit);
+            let last = ahead.iter().min_by(|a, b| {
+                let from_start = |vehicle: &Vehicle| {
+                    let (x, y) = vehicle.center();
+                    (x - start.x as f64).abs() + (y - start.y as f64).abs()
+                };
+                from_start(a).total_cmp(&from_start(b))
+            });
+            if let Some(other) = last
+                && taken + room(self) > length
+            {
+                hold_at(Blocker::Vehicle(other.id), self.distance_to(&stop_line));
+            }
+        }
+
+        // A left turn crosses the lane of oncoming traffic inside the box. It
+        // waits there until whatever is coming is at least `left_turn_gap`
+        // seconds away at its top speed. Traffic held at a red light or standing
+        // still doesn't count, unless it's in the way already, turned into its
+        // exit ahead of it for instance. One too long to turn without reaching
+        // into the oncoming lane waits short of the corner instead.
+        let conflict = sim.roads.movements[self.movement].conflict;
+        let turn = self.next_turn().filter(|(_, turned)| {
+            conflict.is_some_and(|conflict| turned.has_intersection(conflict))
+        });
+        if let Some(conflict) = conflict
+            && (corridor.has_intersection(conflict) || turn.is_some())
+            && !rect.has_intersection(conflict)
+        {
+            let gap = config.left_turn_gap;
+            let oncoming = sim.vehicles_at(self.junction).find(|other| {
+                other.trip.origin == self.trip.origin.opposite()
+                    && (other.rect().has_intersection(conflict)
+                        || (other.start == other.trip.origin
+                            && (other.trip.crossing_tick.is_some()
+                                || other
+                                    .may_pass(&junction.aspect(&approach_light(&other.start))))
+                            && !other.stopped
+                            && (0.0..gap).contains(
+                                &(other.distance_to(&conflict)
+                                    / pixels_per_metre
+                                    / config.class(&other.class).max_speed),
+                            )))
+            });
+            if let Some(other) = oncoming {
+                let distance = match turn {
+                    Some((distance, _)) => distance,
+                    None => self.distance_to(&conflict),
+                };
+                hold_at(Blocker::Vehicle(other.id), distance);
+            }
+        }
+
+        // Oncoming traffic in turn stays off the lane a left turn crosses while
+        // one is on it, stuck behind pedestrians on its way out for instance
+        let crossing = sim.vehicles_at(self.junction).find_map(|other| {
+            let conflict = sim.roads.movements[other.movement].conflict?;
+            (other.direction == Direction::Left
+                && other.trip.origin == self.trip.origin.opposite()
+                && other.rect().has_intersection(conflict)
+                && corridor.has_intersection(conflict)
+                && !rect.has_intersection(conflict))
+            .then_some((other.id, conflict))
+        });
+        if let Some((id, conflict)) = crossing
+            && self.direction != Direction::Left
+        {
+            hold_at(Blocker::Vehicle(id), self.distance_to(&conflict));
+        }
+
+        // Yield to pedestrians on a crosswalk ahead. Those walk beside the
+        // green, so it's mostly turning vehicles that meet them.
+        for (leg, crosswalk) in &junction.crosswalks {
+            if !crosswalk.has_intersection(corridor) || crosswalk.has_intersection(rect) {
+                continue;
+            }
+
+            let pedestrian = sim.pedestrians.iter().find(|pedestrian| {
+                pedestrian.junction == self.junction
+                    && pedestrian.crosswalk == *leg
+                    && pedestrian.start_tick.is_some()
+            });
+            if let Some(pedestrian) = pedestrian {
+                hold_at(
+                    Blocker::Pedestrian(pedestrian.id),
+                    self.distance_to(crosswalk),
+                );
+            }
+        }
+
+        // Keep out of where a vehicle that came first is about to swing round
+        // in the box, unless it waits for this one to pass
+        for other in sim.vehicles_near(area) {
+            if other.id < self.id
+                && other.junction == self.junction
+                && other.blocker != Some(Blocker::Vehicle(self.id))
+                && in_box(other.rect())
+                && let Some((_, turned)) = other.next_turn()
+                && corridor.has_intersection(turned)
+                && !rect.has_intersection(turned)
+            {
+                hold_at(Blocker::Vehicle(other.id), self.distance_to(&turned));
+            }
+        }
 
-            if too_close {
-                return false;
+        // Turning swings the vehicle round on the spot, so it waits short of the
+        // corner until there's room for it the other way round. A long vehicle
+        // waiting there would stand across the lanes it turns over, so it waits
+        // outside the box instead.
+        if in_box(corridor)
+            && let Some((distance, turned)) = self.next_turn()
+            && let Some(other) = sim
+                .vehicles_near(turned)
+                .find(|other| other.id != self.id && other.rect().has_intersection(turned))
+        {
+            if in_box(rect) {
+                hold_at(Blocker::Vehicle(other.id), distance);
+            } else {
+                hold_at(Blocker::Vehicle(other.id), self.distance_to(&area));
             }
         }
 
-        true
+        // Vehicles in the way. Two whose paths cross each see the other ahead,
+        // then the one that came first goes.
+        for other in sim.vehicles_near(corridor) {
+            if self.id == other.id || !other.rect().has_intersection(corridor) {
+                continue;
+            }
+            if other.id < self.id
+                || !rect.has_intersection(other.corridor(other.lookahead(&sim.config)))
+            {
+                obstacles.push(Obstacle {
+                    blocker: Blocker::Vehicle(other.id),
+                    gap: self.gap_to(other).max(0.0) / pixels_per_metre,
+                    // crossing traffic doesn't carry on ahead of it
+                    speed: if other.start == self.start {
+                        other.speed
+                    } else {
+                        0.0
+                    },
+                    min_gap: config.safety_distance,
+                });
+            }
+        }
+
+        obstacles.into_iter().min_by(|a, b| {
+            self.acceleration(config, Some(a))
+                .total_cmp(&self.acceleration(config, Some(b)))
+        })
     }
 
-    pub fn is_in_area(&self, area: &Rect) -> bool {
-        let car_center_x = self.x + (self.width as i32) / 2;
-        let car_center_y = self.y + (self.height as i32) / 2;
-        let area_center_x = area.x() + (area.width() as i32) / 2;
-        let area_center_y = area.y() + (area.height() as i32) / 2;
+    // Whether a signal showing `aspect` lets the vehicle through
+    pub fn may_pass(&self, aspect: &Aspect) -> bool {
+        match aspect {
+            Aspect::Green => true,
+            Aspect::LeftArrow => self.direction == Direction::Left,
+            Aspect::Amber => self.clearing,
+            Aspect::Red => false,
+        }
+    }
 
-        let small_car = Rect::new(car_center_x, car_center_y, 3, 3);
-        let small_area = Rect::new(area_center_x, area_center_y, 3, 3);
+    // Pixels between the front of the vehicle and `rect` ahead of it,
+    // negative once it reached it
+    pub fn distance_to(&self, rect: &Rect) -> f64 {
+        self.distance_to_edges(
+            rect.x() as f64,
+            rect.y() as f64,
+            rect.right() as f64,
+            rect.bottom() as f64,
+        )
+    }
 
-        small_area.has_intersection(small_car)
+    // The same up to the rear of `other`, or whichever side it shows
+    pub fn gap_to(&self, other: &Vehicle) -> f64 {
+        self.distance_to_edges(
+            other.x,
+            other.y,
+            other.x + other.width as f64,
+            other.y + other.height as f64,
+        )
     }
 
-    pub fn is_in_area2(&self, area: &Rect) -> bool {
-        let car = Rect::new(self.x, self.y, self.width, self.height);
+    fn distance_to_edges(&self, left: f64, top: f64, right: f64, bottom: f64) -> f64 {
+        match self.start {
+            Position::Top => top - (self.y + self.height as f64),
+            Position::Right => self.x - right,
+            Position::Bottom => self.y - bottom,
+            Position::Left => left - (self.x + self.width as f64),
+        }
+    }
 
-        area.has_intersection(car)
+    // Metres it takes to stop from its current speed, braking comfortably
+    pub fn braking_distance(&self, config: &VehicleConfig) -> f64 {
+        self.speed * self.speed / (2.0 * config.class(&self.class).deceleration)
+    }
+
+    // Swaps length and width around the center, for when the vehicle turns
+    pub fn rotate(&mut self) {
+        let center = self.center();
+        std::mem::swap(&mut self.width, &mut self.height);
+        self.set_center(center);
+    }
+
+    // It drove out of `area`, through the side it was heading to
+    pub fn is_past(&self, area: &Rect) -> bool {
+        let rect = self.rect();
+        match self.start {
+            Position::Top => rect.y() > area.bottom(),
+            Position::Bottom => rect.bottom() < area.y(),
+            Position::Left => rect.x() > area.right(),
+            Position::Right => rect.right() < area.x(),
+        }
+    }
+
+    pub fn is_in_area2(&self, area: &Rect) -> bool {
+        area.has_intersection(self.rect())
     }
 }
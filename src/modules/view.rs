@@ -4,267 +4,343 @@ use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 
+use crate::modules::config::Config;
+use crate::modules::geometry;
+use crate::modules::junction::Junction;
 use crate::modules::lights::*;
+use crate::modules::pedestrians::*;
+use crate::modules::simulation::Simulation;
 use crate::modules::vehicle::*;
 
-#[derive(Clone)]
+// Renders a `Simulation` on an SDL canvas. It only reads the simulation state.
 pub struct View {
-    pub vehicles: Vec<Vehicle>,
-    pub green_light: GreenLight,
-    pub width: u32,
-    pub height: u32,
-    pub center: Point,
-    pub road_size: u32,
     pub light_width: i32,
     pub light_height: i32,
     pub lights_margin: i32,
-    pub cool_downs: CoolDown,
-    pub light_timer: usize,
-    pub minimum_light_time_passed: bool,
-    pub decision_areas: [(DecisionAreas, Rect); 4],
-    pub stop_lines: [(GreenLight, Rect); 4],
-}
-
-#[derive(Clone)]
-pub enum DecisionAreas {
-    TopLeft,
-    TopRight,
-    BottomRight,
-    BottomLeft,
 }
 
 impl View {
-    pub fn new(canvas: &Canvas<Window>) -> Self {
-        let (width, height) = canvas.output_size().expect("Failed to get canvas size");
-        let center = Point::new((width / 2) as i32, (height / 2) as i32);
-        let road_size = 40;
-        let (lights_margin, light_width, light_height) = (5, 22, 35);
-        let decision_areas: [(DecisionAreas, Rect); 4] =
-            Self::get_decision_areas(&center, &road_size);
-        let stop_lines: [(GreenLight, Rect); 4] = Self::get_stop_lines(&decision_areas);
-
+    pub fn new(config: &Config) -> Self {
         Self {
-            vehicles: Vec::new(),
-            green_light: GreenLight::BottomLeft,
-            cool_downs: CoolDown {
-                top: 0,
-                right: 0,
-                bottom: 0,
-                left: 0,
-            },
-            light_timer: 0,
-            minimum_light_time_passed: false,
-            width,
-            height,
-            center,
-            road_size,
-            lights_margin,
-            light_width,
-            light_height,
-            decision_areas,
-            stop_lines,
+            lights_margin: config.lights.margin,
+            light_width: config.lights.width,
+            light_height: config.lights.height,
         }
     }
 
-    pub fn draw(&self, canvas: &mut Canvas<Window>) {
+    pub fn draw(&self, canvas: &mut Canvas<Window>, sim: &Simulation) {
         // Clear background
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
 
-        // Draw roads and lights using view
+        // Draw roads and lights
         canvas.set_draw_color(Color::RGB(255, 255, 255));
-        self.draw_roads(canvas);
+        self.draw_roads(canvas, sim);
 
-        draw_lights(canvas, self);
+        for junction in &sim.junctions {
+            self.draw_lights(canvas, junction, sim.road_size);
+        }
+
+        // for debugging
+        for area in &sim.roads.boxes {
+            canvas.set_draw_color(Color::RGB(66, 0, 0));
+            canvas.fill_rect(to_sdl_rect(area)).unwrap();
+        }
+        // for lane in &sim.roads.lanes {
+        //     if let Some(stop_line) = lane.stop_line() {
+        //         canvas.set_draw_color(Color::RGB(25, 155, 55));
+        //         canvas.draw_rect(to_sdl_rect(&stop_line)).unwrap();
+        //     }
+        // }
+
+        for vehicle in &sim.vehicles {
+            draw_vehicle(canvas, vehicle);
+        }
+
+        // the ones holding each other up in a gridlock
+        if let Some(gridlock) = sim.gridlock.current() {
+            canvas.set_draw_color(Color::RED);
+            for vehicle in &sim.vehicles {
+                if gridlock.cycle.contains(&vehicle.id) {
+                    let body = vehicle.rect();
+                    let (width, height) = (body.width() + 6, body.height() + 6);
+                    let outline = Rect::new(body.x() - 3, body.y() - 3, width, height);
+                    canvas.draw_rect(outline).unwrap();
+                }
+            }
+        }
+
+        canvas.set_draw_color(Color::RGB(240, 240, 240));
+        for pedestrian in &sim.pedestrians {
+            canvas.fill_rect(to_sdl_rect(&pedestrian.rect())).unwrap();
+        }
     }
 
-    fn draw_roads(&self, canvas: &mut Canvas<Window>) {
-        let w = self.width as i32;
-        let h = self.height as i32;
-        let cx = self.center.x;
-        let cy = self.center.y;
-        let r = self.road_size as i32;
+    // The roads of a grid network run across the whole window, through every
+    // junction in their column or row
+    fn draw_roads(&self, canvas: &mut Canvas<Window>, sim: &Simulation) {
+        let w = sim.width as i32;
+        let h = sim.height as i32;
+        let r = sim.road_size as i32;
 
-        // Vertical lines
-        canvas
-            .draw_line(Point::new(cx, 0), Point::new(cx, h))
-            .unwrap();
-        canvas
-            .draw_line(Point::new(cx - r, 0), Point::new(cx - r, h))
-            .unwrap();
-        canvas
-            .draw_line(Point::new(cx + r,     // Add a method to handle light timing logic
-0), Point::new(cx + r, h))
-            .unwrap();
+        for junction in &sim.junctions {
+            let cx = junction.center.x;
+            let cy = junction.center.y;
 
-        // Horizontal lines
-        canvas
-            .draw_line(Point::new(0, cy), Point::new(w, cy))
-            .unwrap();
-        canvas
-            .draw_line(Point::new(0, cy - r), Point::new(w, cy - r))
-            .unwrap();
-        canvas
-            .draw_line(Point::new(0, cy + r), Point::new(w, cy + r))
-            .unwrap();
+            // Vertical lines
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+            canvas
+                .draw_line(Point::new(cx, 0), Point::new(cx, h))
+                .unwrap();
+            canvas
+                .draw_line(Point::new(cx - r, 0), Point::new(cx - r, h))
+                .unwrap();
+            canvas
+                .draw_line(Point::new(cx + r, 0), Point::new(cx + r, h))
+                .unwrap();
+
+            // Horizontal lines
+            canvas
+                .draw_line(Point::new(0, cy), Point::new(w, cy))
+                .unwrap();
+            canvas
+                .draw_line(Point::new(0, cy - r), Point::new(w, cy - r))
+                .unwrap();
+            canvas
+                .draw_line(Point::new(0, cy + r), Point::new(w, cy + r))
+                .unwrap();
+        }
+
+        for junction in &sim.junctions {
+            for (leg, crosswalk) in &junction.crosswalks {
+                let signal = junction.walk_signal(leg, sim.tick, &sim.config);
+                draw_crosswalk(canvas, crosswalk);
+                draw_walk_signal(canvas, crosswalk, &signal, sim.time());
+            }
+        }
     }
 
-    fn get_decision_areas(center: &Point, road_size: &u32) -> [(DecisionAreas, Rect); 4] {
-        [
+    fn draw_lights(&self, canvas: &mut Canvas<Window>, junction: &Junction, road_size: u32) {
+        let road_size = road_size as i32;
+        let light_w = self.light_width;
+        let light_h = self.light_height;
+        let margin = self.lights_margin;
+
+        // box dimensions
+        let box_w = light_w + 10;
+        let box_h = light_h + 10;
+
+        let lights = [
             (
-                DecisionAreas::TopLeft,
+                GreenLight::TopLeft,
                 Rect::new(
-                    center.x - *road_size as i32,
-                    center.y - *road_size as i32,
-                    *road_size,
-                    *road_size,
+                    junction.center.x - road_size - box_w - margin,
+                    junction.center.y - road_size - box_h - margin,
+                    box_w as u32,
+                    box_h as u32,
                 ),
             ),
             (
-                DecisionAreas::TopRight,
+                GreenLight::TopRight,
                 Rect::new(
-                    center.x,
-                    center.y - *road_size as i32,
-                    *road_size,
-                    *road_size,
+                    junction.center.x + road_size + margin,
+                    junction.center.y - road_size - box_h - margin,
+                    box_w as u32,
+                    box_h as u32,
                 ),
             ),
             (
-                DecisionAreas::BottomLeft,
+                GreenLight::BottomRight,
                 Rect::new(
-                    center.x - *road_size as i32,
-                    center.y,
-                    *road_size,
-                    *road_size,
+                    junction.center.x + road_size + margin,
+                    junction.center.y + road_size + margin,
+                    box_w as u32,
+                    box_h as u32,
                 ),
             ),
             (
-                DecisionAreas::BottomRight,
-                Rect::new(center.x, center.y, *road_size, *road_size),
+                GreenLight::BottomLeft,
+                Rect::new(
+                    junction.center.x - road_size - box_w - margin,
+                    junction.center.y + road_size + margin,
+                    box_w as u32,
+                    box_h as u32,
+                ),
             ),
-        ]
-    }
+        ];
 
-    fn get_stop_lines(decision_areas: &[(DecisionAreas, Rect); 4]) -> [(GreenLight, Rect); 4] {
-        decision_areas.clone().map(|(area, rect)| match area {
-            DecisionAreas::TopLeft => {
-                let x = rect.x() + rect.width() as i32 * 2 + 5;
-                let y = rect.y();
-                let height = rect.height() as i32;
-                (
-                    decision_area_to_light(&area),
-                    Rect::new(x - 10, y, 20, height as u32),
-                )
-            }
-            DecisionAreas::TopRight => {
-                let y = rect.y() + rect.width() as i32 * 2 + 5;
-                let x = rect.x();
-                let width = rect.width() as i32;
-                (
-                    decision_area_to_light(&area),
-                    Rect::new(x, y - 10, width as u32, 20),
-                )
-            }
-            DecisionAreas::BottomRight => {
-                let x = rect.x() - rect.width() as i32 - 5;
-                let y = rect.y();
-                let height = rect.height() as i32;
-                (
-                    decision_area_to_light(&area),
-                    Rect::new(x - 10, y, 20, height as u32),
-                )
-            }
-            DecisionAreas::BottomLeft => {
-                let y = rect.y() - rect.width() as i32 - 5;
-                let x = rect.x();
-                let width = rect.width() as i32;
+        for (position, box_rect) in lights {
+            // Draw box background
+            canvas.set_draw_color(Color::RGB(30, 30, 30)); // dark gray box
+            canvas.fill_rect(box_rect).unwrap();
+
+            // three lamps stacked top to bottom, only the shown one lit
+            let lamp_h = (light_h - 4) / 3;
+            let aspect = junction.aspect(&position);
+            let lamps = [
+                (Aspect::Red, Color::RGB(255, 0, 0), Color::RGB(60, 0, 0)),
                 (
-                    decision_area_to_light(&area),
-                    Rect::new(x, y - 10, width as u32, 20),
-                )
-            }
-        })
-    }
+                    Aspect::Amber,
+                    Color::RGB(255, 190, 0),
+                    Color::RGB(60, 45, 0),
+                ),
+                (Aspect::Green, Color::RGB(0, 255, 0), Color::RGB(0, 60, 0)),
+            ];
 
-    pub fn update_light_timing(&mut self, vehicle_in_decision_area: bool) {
-        const MINIMUM_LIGHT_TIME: usize = 100;
+            for (i, (lamp, lit, dim)) in lamps.into_iter().enumerate() {
+                let lamp_rect = Rect::new(
+                    box_rect.x() + 5,
+                    box_rect.y() + 5 + i as i32 * (lamp_h + 2),
+                    light_w as u32,
+                    lamp_h as u32,
+                );
 
-        self.light_timer += 1;
+                canvas.set_draw_color(if lamp == aspect { lit } else { dim });
+                canvas.fill_rect(lamp_rect).unwrap();
+            }
 
-        if self.light_timer >= MINIMUM_LIGHT_TIME {
-            self.minimum_light_time_passed = true;
-        }
+            // a left arrow lights up on the dark green lamp
+            if aspect == Aspect::LeftArrow {
+                let x = box_rect.x() + 5;
+                let y = box_rect.y() + 5 + 2 * (lamp_h + 2) + lamp_h / 2;
+                let head = lamp_h / 2 - 1;
 
-        if self.minimum_light_time_passed && !vehicle_in_decision_area {
-            self.change_light();
+                canvas.set_draw_color(Color::RGB(0, 255, 0));
+                for offset in 0..2 {
+                    let tip = Point::new(x + 2, y + offset);
+                    let lines = [
+                        Point::new(x + light_w - 3, y + offset),
+                        Point::new(x + 2 + head, y + offset - head),
+                        Point::new(x + 2 + head, y + offset + head),
+                    ];
+                    for end in lines {
+                        canvas.draw_line(tip, end).unwrap();
+                    }
+                }
+            }
         }
     }
+}
 
-    fn change_light(&mut self) {
-        self.green_light = match self.green_light {
-            GreenLight::TopLeft => GreenLight::TopRight,
-            GreenLight::TopRight => GreenLight::BottomRight,
-            GreenLight::BottomRight => GreenLight::BottomLeft,
-            GreenLight::BottomLeft => GreenLight::TopLeft,
-        };
+// Zebra stripes along the crosswalk, across the direction of traffic
+fn draw_crosswalk(canvas: &mut Canvas<Window>, crosswalk: &geometry::Rect) {
+    let horizontal = crosswalk.width() > crosswalk.height();
+    let length = crosswalk.width().max(crosswalk.height()) as i32;
 
-        // Reset for next cycle
-        self.light_timer = 0;
-        self.minimum_light_time_passed = false;
+    canvas.set_draw_color(Color::RGB(200, 200, 200));
+    for offset in (2..length - 2).step_by(8) {
+        let stripe = if horizontal {
+            Rect::new(crosswalk.x() + offset, crosswalk.y(), 4, crosswalk.height())
+        } else {
+            Rect::new(crosswalk.x(), crosswalk.y() + offset, crosswalk.width(), 4)
+        };
+        canvas.fill_rect(stripe).unwrap();
     }
 }
 
-pub fn decision_area_to_light(area: &DecisionAreas) -> GreenLight {
-    match area {
-        DecisionAreas::TopLeft => GreenLight::TopRight,
-        DecisionAreas::TopRight => GreenLight::BottomRight,
-        DecisionAreas::BottomRight => GreenLight::BottomLeft,
-        DecisionAreas::BottomLeft => GreenLight::TopLeft,
+// A small lamp past both ends of the crosswalk: white for walk, orange for
+// don't walk, blinking three times a second while flashing
+fn draw_walk_signal(
+    canvas: &mut Canvas<Window>,
+    crosswalk: &geometry::Rect,
+    signal: &WalkSignal,
+    time: f64,
+) {
+    let color = match signal {
+        WalkSignal::Walk => Color::RGB(255, 255, 255),
+        WalkSignal::FlashingDontWalk if ((time * 3.0) as u64).is_multiple_of(2) => {
+            Color::RGB(60, 30, 0)
+        }
+        _ => Color::RGB(255, 120, 0),
+    };
+
+    let size = 6;
+    let gap = PEDESTRIAN_SIZE as i32 + 4;
+    let ends = if crosswalk.width() > crosswalk.height() {
+        let y = crosswalk.y() + (crosswalk.height() as i32 - size) / 2;
+        [
+            (crosswalk.x() - gap - size, y),
+            (crosswalk.right() + gap, y),
+        ]
+    } else {
+        let x = crosswalk.x() + (crosswalk.width() as i32 - size) / 2;
+        [
+            (x, crosswalk.y() - gap - size),
+            (x, crosswalk.bottom() + gap),
+        ]
+    };
+
+    canvas.set_draw_color(color);
+    for (x, y) in ends {
+        canvas
+            .fill_rect(Rect::new(x, y, size as u32, size as u32))
+            .unwrap();
     }
 }
 
-pub fn decide_direction(
-    vehicle: &mut Vehicle,
-    decision_areas: &[(DecisionAreas, sdl2::rect::Rect); 4],
-) {
-    if vehicle.decision_made || vehicle.direction == Direction::Straight {
-        return;
-    }
+// Colored by direction. Bicycles are an open frame, trucks have a line
+// behind the cab, buses a light stripe along the roof and emergency vehicles a
+// red and blue light bar across it.
+fn draw_vehicle(canvas: &mut Canvas<Window>, vehicle: &Vehicle) {
+    let color = match vehicle.direction {
+        Direction::Straight => Color::RGB(255, 165, 0),
+        Direction::Right => Color::CYAN,
+        Direction::Left => Color::MAGENTA,
+    };
 
-    for (decision, area) in decision_areas {
-        if vehicle.is_in_area(area) {
-            match (&vehicle.start, &vehicle.direction, decision) {
-                // Turning LEFT (Lmovya)
-                (Position::Top, Direction::Left, DecisionAreas::BottomLeft) => {
-                    vehicle.start = Position::Left;
-                }
-                (Position::Right, Direction::Left, DecisionAreas::TopLeft) => {
-                    vehicle.start = Position::Top;
-                }
-                (Position::Bottom, Direction::Left, DecisionAreas::TopRight) => {
-                    vehicle.start = Position::Right;
-                }
-                (Position::Left, Direction::Left, DecisionAreas::BottomRight) => {
-                    vehicle.start = Position::Bottom;
-                }
+    canvas.set_draw_color(color);
+    let body = to_sdl_rect(&vehicle.rect());
+    match vehicle.class {
+        VehicleClass::Bicycle => canvas.draw_rect(body).unwrap(),
+        _ => canvas.fill_rect(body).unwrap(),
+    }
 
-                // Turning RIGHT (Lkhadra)
-                (Position::Right, Direction::Right, DecisionAreas::TopRight) => {
-                    vehicle.start = Position::Bottom;
-                }
-                (Position::Top, Direction::Right, DecisionAreas::TopLeft) => {
-                    vehicle.start = Position::Right;
-                }
-                (Position::Bottom, Direction::Right, DecisionAreas::BottomRight) => {
-                    vehicle.start = Position::Left;
+    let (x, y, width, height) = (body.x(), body.y(), body.width(), body.height());
+    let (w, h) = (width as i32, height as i32);
+    match vehicle.class {
+        VehicleClass::Truck => {
+            let cab = 14.min(w.max(h) / 2);
+            let line = match vehicle.start {
+                Position::Top => Rect::new(x, y + h - cab, width, 2),
+                Position::Bottom => Rect::new(x, y + cab - 2, width, 2),
+                Position::Left => Rect::new(x + w - cab, y, 2, height),
+                Position::Right => Rect::new(x + cab - 2, y, 2, height),
+            };
+            canvas.set_draw_color(Color::RGB(40, 40, 40));
+            canvas.fill_rect(line).unwrap();
+        }
+        VehicleClass::Bus => {
+            let stripe = match vehicle.start {
+                Position::Top | Position::Bottom => {
+                    Rect::new(x + w / 2 - 3, y + 4, 6, height.saturating_sub(8))
                 }
-                (Position::Left, Direction::Right, DecisionAreas::BottomLeft) => {
-                    vehicle.start = Position::Top;
+                Position::Left | Position::Right => {
+                    Rect::new(x + 4, y + h / 2 - 3, width.saturating_sub(8), 6)
                 }
-
-                _ => continue,
-            }
-            vehicle.decision_made = true;
+            };
+            canvas.set_draw_color(Color::RGB(240, 240, 240));
+            canvas.fill_rect(stripe).unwrap();
         }
+        VehicleClass::Emergency => {
+            let (red, blue) = match vehicle.start {
+                Position::Top | Position::Bottom => (
+                    Rect::new(x + 2, y + h / 2 - 3, (width / 2).saturating_sub(2), 6),
+                    Rect::new(x + w / 2, y + h / 2 - 3, (width / 2).saturating_sub(2), 6),
+                ),
+                Position::Left | Position::Right => (
+                    Rect::new(x + w / 2 - 3, y + 2, 6, (height / 2).saturating_sub(2)),
+                    Rect::new(x + w / 2 - 3, y + h / 2, 6, (height / 2).saturating_sub(2)),
+                ),
+            };
+            canvas.set_draw_color(Color::RED);
+            canvas.fill_rect(red).unwrap();
+            canvas.set_draw_color(Color::BLUE);
+            canvas.fill_rect(blue).unwrap();
+        }
+        _ => (),
     }
 }
+
+fn to_sdl_rect(rect: &geometry::Rect) -> Rect {
+    Rect::new(rect.x(), rect.y(), rect.width(), rect.height())
+}